@@ -0,0 +1,181 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    path::PathBuf,
+    process::Command,
+};
+
+use anyhow::{Context, Result, bail};
+use bincode::{Decode, Encode};
+use nix::unistd;
+use serde::Deserialize;
+
+use crate::config;
+
+/// A user that's created if it doesn't exist.
+#[derive(Clone, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+pub struct User {
+    #[serde(default)]
+    uid: Option<u32>,
+    /// GID of the user's primary group.
+    #[serde(default)]
+    gid: Option<u32>,
+    #[serde(default)]
+    home: Option<PathBuf>,
+    #[serde(default)]
+    shell: Option<PathBuf>,
+    /// Supplementary groups of the user.
+    #[serde(default)]
+    groups: Vec<String>,
+}
+
+/// A group that's created if it doesn't exist.
+#[derive(Clone, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Group {
+    #[serde(default)]
+    gid: Option<u32>,
+}
+
+/// Names of the users and groups created by decster that a module declares.
+#[derive(Clone, Decode, Default, Encode)]
+pub struct Accounts {
+    pub users: BTreeSet<String>,
+    pub groups: BTreeSet<String>,
+}
+
+impl Accounts {
+    pub fn new(users: &BTreeMap<String, User>, groups: &BTreeMap<String, Group>) -> Self {
+        Accounts {
+            users: users.keys().cloned().collect(),
+            groups: groups.keys().cloned().collect(),
+        }
+    }
+}
+
+/// Users and groups created by decster, along with the names of the modules
+/// declaring them. Users and groups that already existed aren't included, so
+/// they're never removed, and the others are only removed once no module
+/// declares them.
+#[derive(Decode, Default, Encode)]
+pub struct AccountOwners {
+    users: BTreeMap<String, BTreeSet<String>>,
+    groups: BTreeMap<String, BTreeSet<String>>,
+}
+
+impl AccountOwners {
+    /// Creates groups and then users that don't exist yet. Every declared
+    /// account created by decster, including ones created for other modules,
+    /// is recorded as owned by `module` and added to `accounts`.
+    pub fn create(
+        &mut self,
+        module: &str,
+        accounts: &mut Accounts,
+        users: &BTreeMap<String, User>,
+        groups: &BTreeMap<String, Group>,
+    ) -> Result<()> {
+        for (name, group) in groups {
+            if unistd::Group::from_name(name)?.is_none() {
+                let mut args = vec!["groupadd".to_string()];
+                if let Some(gid) = group.gid {
+                    args.extend(["--gid".to_string(), gid.to_string()]);
+                }
+                args.push(name.clone());
+                run(&args).with_context(|| format!("Couldn't create group `{name}`"))?;
+                self.groups.entry(name.clone()).or_default();
+            }
+            if let Some(owners) = self.groups.get_mut(name) {
+                owners.insert(module.to_string());
+                accounts.groups.insert(name.clone());
+            }
+        }
+        for (name, user) in users {
+            if unistd::User::from_name(name)?.is_none() {
+                let mut args = vec!["useradd".to_string()];
+                if let Some(uid) = user.uid {
+                    args.extend(["--uid".to_string(), uid.to_string()]);
+                }
+                if let Some(gid) = user.gid {
+                    args.extend(["--gid".to_string(), gid.to_string()]);
+                }
+                if let Some(home) = &user.home {
+                    let home = home.to_string_lossy().into_owned();
+                    args.extend(["--create-home".to_string(), "--home-dir".to_string(), home]);
+                }
+                if let Some(shell) = &user.shell {
+                    args.extend(["--shell".to_string(), shell.to_string_lossy().into_owned()]);
+                }
+                if !user.groups.is_empty() {
+                    args.extend(["--groups".to_string(), user.groups.join(",")]);
+                }
+                args.push(name.clone());
+                run(&args).with_context(|| format!("Couldn't create user `{name}`"))?;
+                self.users.entry(name.clone()).or_default();
+            }
+            if let Some(owners) = self.users.get_mut(name) {
+                owners.insert(module.to_string());
+                accounts.users.insert(name.clone());
+            }
+        }
+        Ok(())
+    }
+
+    /// Removes `module` as an owner of every account that isn't in `kept`, and
+    /// then removes users and groups no module owns anymore. Home directories
+    /// are kept.
+    pub fn remove(&mut self, module: &str, kept: &Accounts) -> Result<()> {
+        for name in Self::release(&mut self.users, module, &kept.users) {
+            if unistd::User::from_name(&name)?.is_some() {
+                run(&["userdel".to_string(), name.clone()])
+                    .with_context(|| format!("Couldn't remove user `{name}`"))?;
+            }
+            self.users.remove(&name);
+        }
+        for name in Self::release(&mut self.groups, module, &kept.groups) {
+            if unistd::Group::from_name(&name)?.is_some() {
+                run(&["groupdel".to_string(), name.clone()])
+                    .with_context(|| format!("Couldn't remove group `{name}`"))?;
+            }
+            self.groups.remove(&name);
+        }
+        Ok(())
+    }
+
+    /// Removes `module` as an owner of accounts that aren't in `kept`, and
+    /// returns the accounts that are left without owners.
+    fn release(
+        owners: &mut BTreeMap<String, BTreeSet<String>>,
+        module: &str,
+        kept: &BTreeSet<String>,
+    ) -> Vec<String> {
+        owners
+            .iter_mut()
+            .filter(|(name, _)| !kept.contains(*name))
+            .filter_map(|(name, modules)| {
+                modules.remove(module);
+                modules.is_empty().then(|| name.clone())
+            })
+            .collect()
+    }
+}
+
+fn run(args: &[String]) -> Result<()> {
+    let mut command = match config::root_command() {
+        Some(mut command) => {
+            command.args(args);
+            command
+        }
+        None => {
+            let mut command = Command::new(&args[0]);
+            command.args(&args[1..]);
+            command
+        }
+    };
+    let status = command
+        .status()
+        .with_context(|| format!("Couldn't run `{}`", args[0]))?;
+    if !status.success() {
+        bail!("`{}` failed ({status})", args[0]);
+    }
+    Ok(())
+}
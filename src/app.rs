@@ -1,6 +1,6 @@
 use anyhow::Result;
 
-use crate::{cli, config, env::Env, state::State};
+use crate::{cli, config, env::Env, state::State, upon};
 
 pub struct App {
     pub env: Env,
@@ -12,6 +12,7 @@ impl App {
         let env = Env::load()?;
         let state = State::load(&env)?;
         config::load(&env)?;
+        upon::load(&env);
         let app = App { env, state };
         cli::run(app)
     }
@@ -7,6 +7,7 @@ pub mod alias;
 pub mod common_args;
 pub mod disable;
 pub mod enable;
+pub mod render;
 pub mod run;
 pub mod show;
 pub mod sync;
@@ -21,6 +22,7 @@ pub fn command() -> Command {
         .subcommand(update::command())
         .subcommand(sync::command())
         .subcommand(show::command())
+        .subcommand(render::command())
         .subcommand(run::command())
 }
 
@@ -37,7 +39,6 @@ pub fn command_with_aliases() -> Result<Command> {
 
 pub fn run(app: App) -> Result<()> {
     let matches = command_with_aliases()?.get_matches();
-    let fetch = matches.get_flag("fetch");
     run_inner(app, matches)
 }
 
@@ -49,6 +50,7 @@ fn run_inner(app: App, mut matches: ArgMatches) -> Result<()> {
         "update" => update::run(app, matches)?,
         "sync" => sync::run(app, matches)?,
         "show" => show::run(app, matches)?,
+        "render" => render::run(app, matches)?,
         "run" => run::run(app, matches),
         _ => run_inner(app, alias::matches(&subcommand, matches)?)?,
     }
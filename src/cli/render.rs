@@ -0,0 +1,49 @@
+use std::path::{self, PathBuf};
+
+use anyhow::{Result, anyhow, bail};
+use clap::{ArgMatches, Command, arg};
+use crossterm::style::Stylize;
+
+use crate::{app::App, config, utils::pretty::Pretty};
+
+pub fn command() -> Command {
+    Command::new("render")
+        .about("Print rendered templates without writing them")
+        .arg(arg!(-c --context "Print the context of the module as TOML"))
+        .arg(arg!(<MODULE>))
+        .arg(arg!([PATH]).required_unless_present("context"))
+}
+
+pub fn run(mut app: App, matches: ArgMatches) -> Result<()> {
+    let name = matches.get_one::<String>("MODULE").unwrap().as_str();
+    let path = matches.get_one::<String>("PATH").map(PathBuf::from);
+
+    let (name, module) =
+        config::module(name).ok_or_else(|| anyhow!("Module {} doesn't exist", name.magenta()))?;
+    let modules = module.import(name)?;
+    let context = modules.context(&mut app.env)?;
+    if matches.get_flag("context") {
+        print!("{}", toml::to_string(&context)?);
+    }
+
+    if let Some(path) = path {
+        let path = path::absolute(app.env.untildefy(&path)?)?;
+        let links: Vec<_> = modules.links(&mut app.env, &context)?.collect();
+        let mut found = None;
+        for link in links.iter() {
+            if path.starts_with(app.env.untildefy(link.path())?) {
+                found = Some(link);
+                break;
+            }
+        }
+        let Some(link) = found else {
+            let path = app.env.tildefy(&path);
+            bail!("{} isn't a path in {}", path.pretty(), name.magenta());
+        };
+        print!(
+            "{}",
+            link.render(&mut app.env, &app.state, name, &path, &context)?
+        );
+    }
+    Ok(())
+}
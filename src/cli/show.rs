@@ -3,6 +3,7 @@ use std::{borrow::Cow, fmt::Display, path::Path};
 use anyhow::Result;
 use clap::{ArgMatches, Command, arg};
 use crossterm::style::Stylize;
+use itertools::Itertools;
 use termtree::Tree;
 
 use crate::{app::App, config, globs::Globs, utils::sha256::Sha256Hash};
@@ -30,6 +31,7 @@ pub fn run(app: App, matches: ArgMatches) -> Result<()> {
     let leaves = [
         sources(&app, &globs),
         (!modules.is_empty()).then(|| Tree::new("Modules".into()).with_leaves(modules)),
+        packages(&app, &globs),
     ]
     .into_iter()
     .flatten();
@@ -65,6 +67,31 @@ fn sources<'a>(app: &'a App, globs: &Globs) -> Option<Tree<Cow<'a, str>>> {
     (!leaves.is_empty()).then(|| Tree::new("Sources".into()).with_leaves(leaves))
 }
 
+/// Shows packages along with the modules wanting them. Packages are shown if
+/// either their name or one of their modules matches `globs`.
+fn packages<'a>(app: &'a App, globs: &Globs) -> Option<Tree<Cow<'a, str>>> {
+    let managers: Vec<_> = app
+        .state
+        .package_owners()
+        .into_iter()
+        .filter_map(|(manager, owners)| {
+            let packages: Vec<Cow<_>> = owners
+                .iter()
+                .filter(|(package, modules)| {
+                    globs.is_match(&package.name) || modules.iter().any(|m| globs.is_match(m))
+                })
+                .map(|(package, modules)| {
+                    let modules = modules.iter().map(|m| m.magenta()).join(", ");
+                    format!("{package} ({modules})").into()
+                })
+                .collect();
+            (!packages.is_empty())
+                .then(|| Tree::new(manager.to_string().into()).with_leaves(packages))
+        })
+        .collect();
+    (!managers.is_empty()).then(|| Tree::new("Packages".into()).with_leaves(managers))
+}
+
 fn hash_string<D>(source: D, path: &Path) -> String
 where
     D: Display,
@@ -1,23 +1,58 @@
 use anyhow::Result;
-use clap::{ArgMatches, Command, arg};
+use clap::{ArgAction, ArgMatches, Command, arg};
 
-use crate::app::App;
+use crate::{app::App, globs::Globs, packages::SyncOptions};
 
 pub fn command() -> Command {
     Command::new("sync")
         .about("Update system packages to match enabled modules")
         .arg(arg!(-i --install "Install without uninstalling").conflicts_with("uninstall"))
         .arg(arg!(-u --uninstall "Uninstall without installing"))
+        .arg(arg!(--force "Uninstall without confirmation, even if there are many packages"))
+        .arg(arg!(-n --"dry-run" "Show packages without installing or uninstalling them"))
+        .arg(
+            arg!(-m --module <MODULE> "Only install packages of modules matching a glob")
+                .action(ArgAction::Append)
+                .conflicts_with("uninstall"),
+        )
 }
 
 pub fn run(app: App, matches: ArgMatches) -> Result<()> {
+    let modules: Option<Vec<_>> = matches
+        .get_many::<String>("module")
+        .map(|modules| modules.map(|s| s.as_str()).collect());
+    // Packages of other modules aren't known to be unwanted when only some
+    // modules are synced, so nothing is uninstalled.
     let (install, uninstall) = match (matches.get_flag("install"), matches.get_flag("uninstall")) {
+        _ if modules.is_some() => (true, false),
         (false, false) => (true, true),
         (install, uninstall) => (install, uninstall),
     };
 
-    for (manager, packages) in app.state.packages() {
-        manager.sync(install, uninstall, &packages)?;
+    let options = SyncOptions {
+        install,
+        uninstall,
+        force: matches.get_flag("force"),
+        dry_run: matches.get_flag("dry-run"),
+    };
+    let packages = match modules {
+        Some(modules) => app
+            .state
+            .packages_matching_modules(&Globs::strict(modules)?),
+        None => app.state.packages(),
+    };
+    // Every plan is shown before anything is installed or uninstalled.
+    let plans = packages
+        .iter()
+        .map(|(manager, packages)| manager.plan(options, packages))
+        .collect::<Result<Vec<_>>>()?;
+    for plan in plans.iter() {
+        plan.print();
+    }
+    if !options.dry_run {
+        for plan in plans.iter() {
+            plan.run()?;
+        }
     }
     Ok(())
 }
@@ -8,17 +8,19 @@ use std::{
 
 use anyhow::{Context, Result, bail};
 use serde::Deserialize;
+use toml::Table;
 
 use crate::{
     globs::Globs,
     module::Module,
+    packages::{CustomManager, PackageManager},
     source::{hashable::HashableSource, name::SourceName},
     utils::pretty::Pretty,
 };
 
 use super::env::Env;
 
-#[derive(Default, Deserialize)]
+#[derive(Deserialize)]
 #[serde(deny_unknown_fields, rename_all = "kebab-case")]
 pub struct Config {
     #[serde(default)]
@@ -28,7 +30,17 @@ pub struct Config {
     root_command: Vec<String>,
     #[serde(default)]
     aliases: BTreeMap<String, Vec<String>>,
+    #[serde(default)]
+    package_managers: BTreeMap<String, CustomManager>,
+    #[serde(default)]
+    ignored_packages: Globs,
+    #[serde(default = "Config::default_max_uninstalls")]
+    max_uninstalls: usize,
+    #[serde(default = "Config::default_systemctl_command")]
+    systemctl_command: Vec<String>,
 
+    #[serde(skip, default)]
+    host_context: Table,
     #[serde(skip, default)]
     modules: BTreeMap<String, Module>,
     #[serde(skip, default)]
@@ -40,6 +52,11 @@ pub struct Config {
 impl Config {
     pub fn load(env: &Env) -> Result<Self> {
         let mut config = Config::parse(env.config_file())?;
+        if config.systemctl_command.is_empty() {
+            bail!("`systemctl-command` must not be empty");
+        }
+        config.check_package_managers()?;
+        config.load_host_context(env.host_context_file())?;
         config.load_modules(env.module_dir())?;
         config.load_static_sources(env.static_source_dir())?;
         config.load_dynamic_sources(env.dynamic_source_file())?;
@@ -51,28 +68,61 @@ impl Config {
         P: AsRef<Path>,
     {
         let path = path.as_ref();
-        Ok(fs::read_to_string(path)
-            .ok()
-            .map(|string| toml::from_str(&string))
-            .transpose()
-            .with_context(|| format!("Couldn't parse {}", path.pretty()))?
-            .unwrap_or_default())
+        // A missing file is parsed as an empty one, so every field gets its
+        // default value.
+        let string = fs::read_to_string(path).unwrap_or_default();
+        toml::from_str(&string).with_context(|| format!("Couldn't parse {}", path.pretty()))
     }
 
     fn default_root_command() -> Vec<String> {
         vec!["sudo".to_string()]
     }
 
+    fn default_max_uninstalls() -> usize {
+        10
+    }
+
+    fn default_systemctl_command() -> Vec<String> {
+        vec!["systemctl".to_string()]
+    }
+
+    fn check_package_managers(&self) -> Result<()> {
+        for (name, manager) in self.package_managers.iter() {
+            if !PackageManager::from(name.clone()).is_custom() {
+                bail!("Couldn't overwrite package manager `{name}`");
+            }
+            manager
+                .check()
+                .with_context(|| format!("Package manager `{name}` is invalid"))?;
+        }
+        Ok(())
+    }
+
+    fn load_host_context(&mut self, file: &Path) -> Result<()> {
+        if file.is_file() {
+            self.host_context = toml::from_str(&fs::read_to_string(file)?)
+                .with_context(|| format!("Couldn't parse {}", file.pretty()))?;
+        }
+        Ok(())
+    }
+
     fn load_modules(&mut self, dir: &Path) -> Result<()> {
         if !dir.is_dir() {
             return Ok(());
         }
         crate::fs::walk_dir_rel(dir, false, false, |path, rel_path| {
-            if path.is_file() {
-                if let Some(name) = rel_path.to_string_lossy().strip_suffix(".toml") {
-                    let module = Module::parse(path)?;
-                    self.modules.insert(name.to_string(), module);
+            if path.is_file()
+                && let Some(name) = rel_path.to_string_lossy().strip_suffix(".toml")
+            {
+                let module = Module::parse(path)?;
+                for manager in module.package_managers() {
+                    if let PackageManager::Custom(manager) = manager
+                        && !self.package_managers.contains_key(manager)
+                    {
+                        bail!("Package manager `{manager}` used by module `{name}` isn't defined");
+                    }
                 }
+                self.modules.insert(name.to_string(), module);
             }
             Ok(())
         })?;
@@ -142,6 +192,18 @@ pub fn root_command() -> Option<Command> {
     })
 }
 
+pub fn ignored_packages() -> &'static Globs {
+    &config().ignored_packages
+}
+
+pub fn max_uninstalls() -> usize {
+    config().max_uninstalls
+}
+
+pub fn systemctl_command() -> &'static [String] {
+    &config().systemctl_command
+}
+
 pub fn alias(name: &str) -> Result<impl Iterator<Item = &str>> {
     config().alias(name)
 }
@@ -153,6 +215,14 @@ pub fn aliases() -> impl Iterator<Item = (&'static str, impl Iterator<Item = &'s
         .map(|(alias, command)| (alias.as_str(), command.iter().map(|s| s.as_str())))
 }
 
+pub fn host_context() -> &'static Table {
+    &config().host_context
+}
+
+pub fn package_manager(name: &str) -> Option<&'static CustomManager> {
+    config().package_managers.get(name)
+}
+
 pub fn has_static_source(name: &SourceName) -> bool {
     config().static_sources.contains(name)
 }
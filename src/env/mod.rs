@@ -20,6 +20,12 @@ impl Env {
         })
     }
 
+    /// Forgets cached users and groups, so users and groups created since
+    /// they were cached can be found.
+    pub fn reload_users(&mut self) {
+        self.users = Users::default();
+    }
+
     /// Returns user with name `name` if that user isn't the current user.
     pub fn other_user_with_name(&mut self, name: &str) -> Result<Option<&User>> {
         let current_uid = self.users.uid();
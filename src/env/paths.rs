@@ -4,16 +4,20 @@ use std::{
 };
 
 use anyhow::{Result, anyhow};
+use nix::unistd;
 
 pub struct Paths {
     home_dir: PathBuf,
     config_dir: PathBuf,
     config_file: PathBuf,
     module_dir: PathBuf,
+    host_context_file: PathBuf,
+    template_dir: PathBuf,
     static_source_dir: PathBuf,
     dynamic_source_file: PathBuf,
     named_source_dir: PathBuf,
     unnamed_source_dir: PathBuf,
+    backup_dir: PathBuf,
     state_file: PathBuf,
 }
 
@@ -35,6 +39,12 @@ impl Paths {
 
         let config_file = config_dir.join("config.toml");
         let module_dir = config_dir.join("modules");
+        let template_dir = config_dir.join("templates");
+        let mut host_file =
+            unistd::gethostname().map_err(|err| anyhow!("Couldn't determine hostname ({err})"))?;
+        // `with_extension` would replace the last part of dotted hostnames.
+        host_file.push(".toml");
+        let host_context_file = config_dir.join("hosts").join(host_file);
         let static_source_dir = config_dir.join("sources");
         let dynamic_source_file = config_dir.join("sources.toml");
 
@@ -49,10 +59,13 @@ impl Paths {
             config_dir,
             config_file,
             module_dir,
+            host_context_file,
+            template_dir,
             static_source_dir,
             dynamic_source_file,
             named_source_dir,
             unnamed_source_dir,
+            backup_dir: data_dir.join("backups"),
             state_file: data_dir.join("state"),
         })
     }
@@ -73,6 +86,14 @@ impl Paths {
         &self.module_dir
     }
 
+    pub fn host_context_file(&self) -> &Path {
+        &self.host_context_file
+    }
+
+    pub fn template_dir(&self) -> &Path {
+        &self.template_dir
+    }
+
     pub fn static_source_dir(&self) -> &Path {
         &self.static_source_dir
     }
@@ -89,6 +110,10 @@ impl Paths {
         &self.unnamed_source_dir
     }
 
+    pub fn backup_dir(&self) -> &Path {
+        &self.backup_dir
+    }
+
     pub fn state_file(&self) -> &Path {
         &self.state_file
     }
@@ -15,19 +15,61 @@ use thiserror::Error;
 
 use crate::{env::Env, utils::pretty::Pretty};
 
+/// File permissions, including the setuid, setgid and sticky bits. Modes are
+/// either absolute, or relative to the current permissions of a path, like the
+/// symbolic modes of `chmod`.
 #[derive(Clone, Copy, Debug, Eq, Ord, PartialEq, PartialOrd)]
-pub struct Mode(u16);
+pub struct Mode {
+    set: u16,
+    clear: u16,
+}
 
 impl Mode {
     const OFF_CHAR: char = '-';
     const ON_CHARS: [char; 3] = ['r', 'w', 'x'];
+    const SPECIAL_CHARS: [(char, char); 3] = [('s', 'S'), ('s', 'S'), ('t', 'T')];
+    const ALL_BITS: u16 = 0o7777;
+    const CLASSES: [(char, u16); 3] = [('u', 0o4700), ('g', 0o2070), ('o', 0o1007)];
+    const PERMS: [(char, u16); 5] = [
+        ('r', 0o444),
+        ('w', 0o222),
+        ('x', 0o111),
+        ('s', 0o6000),
+        ('t', 0o1000),
+    ];
+
+    pub const fn absolute(bits: u16) -> Self {
+        Mode {
+            set: bits & Self::ALL_BITS,
+            clear: Self::ALL_BITS,
+        }
+    }
 
     pub fn from_metadata(metadata: &Metadata) -> Self {
-        Mode(metadata.mode() as u16)
+        Mode::absolute(metadata.mode() as u16)
+    }
+
+    fn is_absolute(&self) -> bool {
+        self.clear == Self::ALL_BITS
+    }
+
+    /// Returns the permission bits that result from applying the mode to
+    /// `bits`.
+    pub fn apply(&self, bits: u16) -> u16 {
+        (bits & Self::ALL_BITS & !self.clear) | self.set
     }
 
     pub fn set(&self, env: &Env, path: &Path) -> Result<()> {
-        fs::set_permissions(path, Permissions::from_mode(self.0 as u32)).with_context(|| {
+        let bits = match self.is_absolute() {
+            true => self.set,
+            false => {
+                let md = fs::metadata(path).with_context(|| {
+                    format!("Couldn't read metadata of {}", env.tildefy(path).pretty())
+                })?;
+                self.apply(md.mode() as u16)
+            }
+        };
+        fs::set_permissions(path, Permissions::from_mode(bits as u32)).with_context(|| {
             format!(
                 "Couldn't set mode of {} to {self}",
                 env.tildefy(path).pretty()
@@ -35,23 +77,130 @@ impl Mode {
         })
     }
 
-    fn parse_char(index: usize, char: char) -> Result<bool, ParseModeError> {
+    /// Parses one of the 9 characters of an `ls`-style mode string. Execute
+    /// characters also encode the special bit of their class.
+    fn parse_char(index: usize, char: char) -> Result<u16, ParseModeError> {
         debug_assert!((0..9).contains(&index));
+        let bit = 1 << (8 - index);
+        let special = 0o1000 << (2 - index / 3);
+        let (on, (special_on, special_off)) =
+            (Self::ON_CHARS[index % 3], Self::SPECIAL_CHARS[index / 3]);
         Ok(match char {
-            Self::OFF_CHAR => false,
-            _ if char == Self::ON_CHARS[index % 3] => true,
+            Self::OFF_CHAR => 0,
+            _ if char == on => bit,
+            _ if index % 3 == 2 && char == special_on => bit | special,
+            _ if index % 3 == 2 && char == special_off => special,
             _ => Err(ParseModeError::InvalidChar { char, index })?,
         })
     }
+
+    fn parse_string(s: &str) -> Result<Self, ParseModeError> {
+        if s.chars().count() != 9 {
+            Err(ParseModeError::InvalidLength)?;
+        }
+        let bits = s
+            .chars()
+            .enumerate()
+            .map(|(i, char)| Mode::parse_char(i, char))
+            .try_fold(0, |bits, bit| bit.map(|bit| bits | bit))?;
+        Ok(Mode::absolute(bits))
+    }
+
+    /// Parses a comma-separated list of `chmod`-style clauses, like
+    /// `u+x,go-w`. Clauses without classes apply to every class.
+    fn parse_symbolic(s: &str) -> Result<Self, ParseModeError> {
+        let mut mode = Mode { set: 0, clear: 0 };
+        for clause in s.split(',') {
+            let invalid = || ParseModeError::InvalidClause(clause.to_string());
+            let (who, mut actions) =
+                clause.split_at(clause.find(['+', '-', '=']).ok_or_else(invalid)?);
+            let mut classes = 0;
+            for char in who.chars() {
+                classes |= match char {
+                    'a' => Self::ALL_BITS,
+                    _ => {
+                        Self::CLASSES
+                            .iter()
+                            .find(|(c, _)| *c == char)
+                            .ok_or_else(invalid)?
+                            .1
+                    }
+                };
+            }
+            if classes == 0 {
+                classes = Self::ALL_BITS;
+            }
+            while let Some(op) = actions.chars().next() {
+                let end = actions[1..]
+                    .find(['+', '-', '='])
+                    .map_or(actions.len(), |i| i + 1);
+                let mut bits = 0;
+                for char in actions[1..end].chars() {
+                    bits |= Self::PERMS
+                        .iter()
+                        .find(|(c, _)| *c == char)
+                        .ok_or_else(invalid)?
+                        .1;
+                }
+                bits &= classes;
+                match op {
+                    '+' => (mode.set, mode.clear) = (mode.set | bits, mode.clear & !bits),
+                    '-' => (mode.set, mode.clear) = (mode.set & !bits, mode.clear | bits),
+                    _ => {
+                        (mode.set, mode.clear) = (mode.set & !classes | bits, mode.clear | classes)
+                    }
+                }
+                actions = &actions[end..];
+            }
+        }
+        Ok(mode)
+    }
+
+    fn fmt_perms(bits: u16) -> String {
+        Self::PERMS
+            .iter()
+            .filter(|(_, perm)| bits & perm != 0)
+            .map(|(char, _)| char)
+            .collect()
+    }
+
+    fn fmt_symbolic(&self, f: &mut Formatter) -> fmt::Result {
+        let mut clauses = Vec::new();
+        for (who, class) in Self::CLASSES {
+            let (set, clear) = (self.set & class, self.clear & class);
+            if clear == class {
+                clauses.push(format!("{who}={}", Self::fmt_perms(set)));
+                continue;
+            }
+            if clear & !set != 0 {
+                clauses.push(format!("{who}-{}", Self::fmt_perms(clear & !set)));
+            }
+            if set != 0 {
+                clauses.push(format!("{who}+{}", Self::fmt_perms(set)));
+            }
+        }
+        match clauses.is_empty() {
+            true => "a+".fmt(f),
+            false => clauses.join(",").fmt(f),
+        }
+    }
 }
 
 impl Display for Mode {
     fn fmt(&self, f: &mut Formatter) -> fmt::Result {
-        let bits = self.0;
+        if !self.is_absolute() {
+            return self.fmt_symbolic(f);
+        }
+        let bits = self.set;
         for i in 0..9 {
-            match ((bits >> (8 - i)) & 1) != 0 {
-                true => Mode::ON_CHARS[i % 3],
-                false => Mode::OFF_CHAR,
+            let on = ((bits >> (8 - i)) & 1) != 0;
+            let special = i % 3 == 2 && ((bits >> (11 - i / 3)) & 1) != 0;
+            let (special_on, special_off) = Mode::SPECIAL_CHARS[i / 3];
+            match (on, special) {
+                (true, false) => Mode::ON_CHARS[i % 3],
+                (true, true) => special_on,
+                (false, true) => special_off,
+                (false, false) => Mode::OFF_CHAR,
             }
             .fmt(f)?;
         }
@@ -61,21 +210,23 @@ impl Display for Mode {
 
 #[derive(Debug, Error, PartialEq)]
 pub enum ParseModeError {
-    #[error("Mode integer isn't in range from 0 to 0o777")]
+    #[error("Mode integer isn't in range from 0 to 0o7777")]
     IntegerOutOfRange,
     #[error("Mode string isn't 9 characters long")]
     InvalidLength,
     #[error("Invalid mode character `{char}` at index {index}")]
     InvalidChar { char: char, index: usize },
+    #[error("Invalid symbolic mode clause `{0}`")]
+    InvalidClause(String),
 }
 
 impl TryFrom<i64> for Mode {
     type Error = ParseModeError;
 
     fn try_from(value: i64) -> Result<Self, Self::Error> {
-        (0..=0o777)
+        (0..=Mode::ALL_BITS as i64)
             .contains(&value)
-            .then_some(Mode(value as u16))
+            .then_some(Mode::absolute(value as u16))
             .ok_or(ParseModeError::IntegerOutOfRange)
     }
 }
@@ -83,16 +234,17 @@ impl TryFrom<i64> for Mode {
 impl FromStr for Mode {
     type Err = ParseModeError;
 
+    /// Parses either an `ls`-style string like `rwsr-x--T`, or symbolic
+    /// clauses like `u+x,go-w`. Strings are symbolic if they contain classes
+    /// or operators other than `-`, or if they start with `-` and aren't 9
+    /// characters long, like `-w`.
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        if s.len() != 9 {
-            Err(ParseModeError::InvalidLength)?;
+        let is_symbolic = s.contains(['u', 'g', 'o', 'a', '+', '=', ','])
+            || (s.starts_with('-') && s.chars().count() != 9);
+        match is_symbolic {
+            true => Mode::parse_symbolic(s),
+            false => Mode::parse_string(s),
         }
-        let bits = s
-            .chars()
-            .enumerate()
-            .map(|(i, char)| Mode::parse_char(i, char).map(|bit| bit as u16))
-            .try_fold(0, |bits, bit| bit.map(|bit| bits << 1 | bit))?;
-        Ok(Mode(bits))
     }
 }
 
@@ -135,7 +287,13 @@ mod tests {
 
     #[test]
     fn from_int() {
-        let ints = [(-1, false), (0, true), (0o777, true), (0o1000, false)];
+        let ints = [
+            (-1, false),
+            (0, true),
+            (0o777, true),
+            (0o7777, true),
+            (0o10000, false),
+        ];
         for (int, is_ok) in ints {
             let result = is_ok
                 .then(|| Mode::try_from(int).unwrap())
@@ -175,33 +333,78 @@ mod tests {
             ("--r------", Err(invalid_char('r', 2))),
             ("w--------", Err(invalid_char('w', 0))),
             ("-x-------", Err(invalid_char('x', 1))),
+            ("rwsr-x---", Ok(0o4750)),
+            ("rwxr-S---", Ok(0o2740)),
+            ("rwxrwxrwt", Ok(0o1777)),
+            ("rw-r--r-T", Ok(0o1644)),
+            ("-s-------", Err(invalid_char('s', 1))),
+            ("--------s", Err(invalid_char('s', 8))),
+            ("--t------", Err(invalid_char('t', 2))),
         ];
         for (string, result) in strings {
             match result {
-                Ok(bits) => assert_eq!(Mode::from_str(&string).unwrap(), Mode(bits)),
-                Err(err) => assert_eq!(Mode::from_str(&string), Err(err)),
+                Ok(bits) => assert_eq!(Mode::from_str(string).unwrap(), Mode::absolute(bits)),
+                Err(err) => assert_eq!(Mode::from_str(string), Err(err)),
             }
         }
     }
 
     #[test]
     fn display() {
-        assert_eq!(Mode(0b000000000).to_string(), "---------");
-        assert_eq!(Mode(0b100000000).to_string(), "r--------");
-        assert_eq!(Mode(0b010000000).to_string(), "-w-------");
-        assert_eq!(Mode(0b001000000).to_string(), "--x------");
-        assert_eq!(Mode(0b000100000).to_string(), "---r-----");
-        assert_eq!(Mode(0b000010000).to_string(), "----w----");
-        assert_eq!(Mode(0b000001000).to_string(), "-----x---");
-        assert_eq!(Mode(0b000000100).to_string(), "------r--");
-        assert_eq!(Mode(0b000000010).to_string(), "-------w-");
-        assert_eq!(Mode(0b000000001).to_string(), "--------x");
-        assert_eq!(Mode(0b100100100).to_string(), "r--r--r--");
-        assert_eq!(Mode(0b010010010).to_string(), "-w--w--w-");
-        assert_eq!(Mode(0b001001001).to_string(), "--x--x--x");
-        assert_eq!(Mode(0b111000000).to_string(), "rwx------");
-        assert_eq!(Mode(0b000111000).to_string(), "---rwx---");
-        assert_eq!(Mode(0b000000111).to_string(), "------rwx");
-        assert_eq!(Mode(0b111111111).to_string(), "rwxrwxrwx");
+        assert_eq!(Mode::absolute(0b000000000).to_string(), "---------");
+        assert_eq!(Mode::absolute(0b100000000).to_string(), "r--------");
+        assert_eq!(Mode::absolute(0b010000000).to_string(), "-w-------");
+        assert_eq!(Mode::absolute(0b001000000).to_string(), "--x------");
+        assert_eq!(Mode::absolute(0b000100000).to_string(), "---r-----");
+        assert_eq!(Mode::absolute(0b000010000).to_string(), "----w----");
+        assert_eq!(Mode::absolute(0b000001000).to_string(), "-----x---");
+        assert_eq!(Mode::absolute(0b000000100).to_string(), "------r--");
+        assert_eq!(Mode::absolute(0b000000010).to_string(), "-------w-");
+        assert_eq!(Mode::absolute(0b000000001).to_string(), "--------x");
+        assert_eq!(Mode::absolute(0b100100100).to_string(), "r--r--r--");
+        assert_eq!(Mode::absolute(0b010010010).to_string(), "-w--w--w-");
+        assert_eq!(Mode::absolute(0b001001001).to_string(), "--x--x--x");
+        assert_eq!(Mode::absolute(0b111000000).to_string(), "rwx------");
+        assert_eq!(Mode::absolute(0b000111000).to_string(), "---rwx---");
+        assert_eq!(Mode::absolute(0b000000111).to_string(), "------rwx");
+        assert_eq!(Mode::absolute(0b111111111).to_string(), "rwxrwxrwx");
+    }
+
+    #[test]
+    fn display_special_bits() {
+        assert_eq!(Mode::absolute(0o4755).to_string(), "rwsr-xr-x");
+        assert_eq!(Mode::absolute(0o2640).to_string(), "rw-r-S---");
+        assert_eq!(Mode::absolute(0o1777).to_string(), "rwxrwxrwt");
+        assert_eq!(Mode::absolute(0o1000).to_string(), "--------T");
+    }
+
+    #[test]
+    fn symbolic() {
+        let modes = [
+            ("u+x", 0o644, 0o744),
+            ("go-w", 0o666, 0o644),
+            ("u+x,go-w", 0o666, 0o744),
+            ("+x", 0o644, 0o755),
+            ("-w", 0o666, 0o444),
+            ("a=r", 0o4777, 0o444),
+            ("o=", 0o777, 0o770),
+            ("g=rx,o-rwx", 0o777, 0o750),
+            ("g+s", 0o770, 0o2770),
+            ("+t", 0o777, 0o1777),
+            ("u-x+s", 0o755, 0o4655),
+        ];
+        for (string, from, to) in modes {
+            assert_eq!(Mode::from_str(string).unwrap().apply(from), to, "{string}");
+        }
+        let invalid = [("u", "u"), ("x+r", "x+r"), ("u+q", "u+q"), ("u+x,", "")];
+        for (string, clause) in invalid {
+            let err = ParseModeError::InvalidClause(clause.to_string());
+            assert_eq!(Mode::from_str(string), Err(err), "{string}");
+        }
+        assert_eq!(
+            Mode::from_str("u+x,go-w").unwrap().to_string(),
+            "u+x,g-w,o-w"
+        );
+        assert_eq!(Mode::from_str("g=rs").unwrap().to_string(), "g=rs");
     }
 }
@@ -2,6 +2,7 @@ use app::App;
 use crossterm::style::Stylize;
 use std::process;
 
+mod accounts;
 mod app;
 mod cli;
 mod config;
@@ -12,6 +13,7 @@ mod globs;
 mod http;
 mod module;
 mod packages;
+mod services;
 mod source;
 mod state;
 mod upon;
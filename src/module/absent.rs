@@ -0,0 +1,99 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result};
+use bincode::{Decode, Encode};
+use serde::Deserialize;
+
+use crate::{env::Env, utils::pretty::Pretty};
+
+/// An `absent` entry, which is either a path or a table containing a path and
+/// whether it's moved into the backup directory instead of being deleted.
+#[derive(Deserialize)]
+#[serde(from = "AbsentEntryDef")]
+pub struct AbsentEntry {
+    pub path: PathBuf,
+    pub backup: bool,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged, deny_unknown_fields)]
+enum AbsentEntryDef {
+    #[serde(rename_all = "kebab-case")]
+    Table {
+        path: PathBuf,
+        #[serde(default)]
+        backup: bool,
+    },
+    Path(PathBuf),
+}
+
+impl From<AbsentEntryDef> for AbsentEntry {
+    fn from(value: AbsentEntryDef) -> Self {
+        match value {
+            AbsentEntryDef::Table { path, backup } => AbsentEntry { path, backup },
+            AbsentEntryDef::Path(path) => AbsentEntry {
+                path,
+                backup: false,
+            },
+        }
+    }
+}
+
+/// How a path declared as absent was removed.
+#[derive(Decode, Encode)]
+pub enum Removal {
+    Deleted,
+    MovedTo(PathBuf),
+}
+
+/// Removes `path` if it exists, or moves it into the backup directory if
+/// `backup` is set. Existing backups of the path are kept. Returns `None` if
+/// `path` doesn't exist.
+pub fn remove(env: &Env, module: &str, path: &Path, backup: bool) -> Result<Option<Removal>> {
+    if !path.exists() && !path.is_symlink() {
+        return Ok(None);
+    }
+    if !backup {
+        crate::fs::remove_all(path)?;
+        println!("Removed {}", env.tildefy(path).pretty());
+        return Ok(Some(Removal::Deleted));
+    }
+
+    let rel_path = path.strip_prefix("/").unwrap_or(path);
+    let backup_path = free_path(env.backup_dir().join(module).join(rel_path));
+    if let Some(parent) = backup_path.parent() {
+        fs::create_dir_all(parent)
+            .with_context(|| format!("Couldn't create path: {}", parent.pretty()))?;
+    }
+    // Renaming fails if the backup directory is on another file system.
+    if fs::rename(path, &backup_path).is_err() {
+        crate::fs::copy_all(path, &backup_path)?;
+        crate::fs::remove_all(path)?;
+    }
+    println!(
+        "Moved {} to {}",
+        env.tildefy(path).pretty(),
+        env.tildefy(&backup_path).pretty()
+    );
+    Ok(Some(Removal::MovedTo(backup_path)))
+}
+
+/// Returns `path`, or `path` with the first number appended to it that makes
+/// it not exist, so earlier backups aren't overwritten.
+fn free_path(path: PathBuf) -> PathBuf {
+    let exists = |path: &Path| path.exists() || path.is_symlink();
+    if !exists(&path) {
+        return path;
+    }
+    (1..)
+        .map(|i| {
+            let mut name = path.as_os_str().to_os_string();
+            name.push(format!(".{i}"));
+            PathBuf::from(name)
+        })
+        .find(|path| !exists(path))
+        .unwrap()
+}
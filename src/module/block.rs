@@ -0,0 +1,168 @@
+use std::{borrow::Cow, ops::Range};
+
+use bincode::{Decode, Encode};
+use serde::Deserialize;
+
+use super::{entry::EntryPermissions, source::ModuleSource};
+
+/// A `blocks` entry, which is either a source or a table containing a source,
+/// the string comments start with in the file and permissions used if the file
+/// is created.
+#[derive(Deserialize)]
+#[serde(from = "BlockEntryDef")]
+pub struct BlockEntry {
+    pub source: ModuleSource,
+    pub comment: String,
+    pub permissions: EntryPermissions,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged, deny_unknown_fields)]
+enum BlockEntryDef {
+    #[serde(rename_all = "kebab-case")]
+    Table {
+        source: ModuleSource,
+        #[serde(default = "BlockEntryDef::default_comment")]
+        comment: String,
+        #[serde(flatten)]
+        permissions: EntryPermissions,
+    },
+    Source(ModuleSource),
+}
+
+impl BlockEntryDef {
+    fn default_comment() -> String {
+        "#".to_string()
+    }
+}
+
+impl From<BlockEntryDef> for BlockEntry {
+    fn from(value: BlockEntryDef) -> Self {
+        match value {
+            BlockEntryDef::Table {
+                source,
+                comment,
+                permissions,
+            } => BlockEntry {
+                source,
+                comment,
+                permissions,
+            },
+            BlockEntryDef::Source(source) => BlockEntry {
+                source,
+                comment: BlockEntryDef::default_comment(),
+                permissions: EntryPermissions::default(),
+            },
+        }
+    }
+}
+
+/// The lines surrounding a block managed by a module inside of a file whose
+/// other contents are left alone.
+#[derive(Clone, Decode, Encode, PartialEq)]
+pub struct BlockMarkers {
+    begin: String,
+    end: String,
+}
+
+/// Byte ranges of a block, with and without its markers.
+struct BlockRange {
+    outer: Range<usize>,
+    inner: Range<usize>,
+}
+
+impl BlockMarkers {
+    pub fn new(module: &str, comment: &str) -> Self {
+        BlockMarkers {
+            begin: format!("{comment} BEGIN decster:{module}"),
+            end: format!("{comment} END decster:{module}"),
+        }
+    }
+
+    fn find(&self, text: &str) -> Option<BlockRange> {
+        let mut offset = 0;
+        let mut begin = None;
+        for line in text.split_inclusive('\n') {
+            let next = offset + line.len();
+            match begin {
+                None if line.trim_end() == self.begin => begin = Some((offset, next)),
+                Some((outer, inner)) if line.trim_end() == self.end => {
+                    return Some(BlockRange {
+                        outer: outer..next,
+                        inner: inner..offset,
+                    });
+                }
+                _ => (),
+            }
+            offset = next;
+        }
+        None
+    }
+
+    /// Returns the contents of the block without its markers.
+    pub fn contents<'t>(&self, text: &'t str) -> Option<&'t str> {
+        self.find(text).map(|range| &text[range.inner])
+    }
+
+    /// Replaces the block with `contents`, or appends it if it doesn't exist.
+    pub fn insert(&self, text: &str, contents: &str) -> String {
+        let block = format!("{}\n{}{}\n", self.begin, normalize(contents), self.end);
+        match self.find(text) {
+            Some(range) => format!(
+                "{}{block}{}",
+                &text[..range.outer.start],
+                &text[range.outer.end..]
+            ),
+            None if text.is_empty() || text.ends_with('\n') => format!("{text}{block}"),
+            None => format!("{text}\n{block}"),
+        }
+    }
+
+    /// Removes the block and its markers, if it exists.
+    pub fn remove(&self, text: &str) -> Option<String> {
+        self.find(text)
+            .map(|range| format!("{}{}", &text[..range.outer.start], &text[range.outer.end..]))
+    }
+}
+
+/// Adds a trailing newline to non-empty contents, which is how contents are
+/// stored inside of a block.
+pub fn normalize(contents: &str) -> Cow<'_, str> {
+    if contents.is_empty() || contents.ends_with('\n') {
+        Cow::Borrowed(contents)
+    } else {
+        Cow::Owned(format!("{contents}\n"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn insert_and_remove_blocks() {
+        let markers = BlockMarkers::new("hosts", "#");
+        let block = "# BEGIN decster:hosts\n127.0.0.1 foo\n# END decster:hosts\n";
+
+        let text = markers.insert("127.0.0.1 localhost", "127.0.0.1 foo");
+        assert_eq!(text, format!("127.0.0.1 localhost\n{block}"));
+        assert_eq!(markers.contents(&text), Some("127.0.0.1 foo\n"));
+        assert_eq!(markers.insert(&text, "127.0.0.1 foo\n"), text);
+
+        let text = format!("a\n{block}b\n");
+        let updated = markers.insert(&text, "127.0.0.1 bar");
+        assert_eq!(
+            updated,
+            "a\n# BEGIN decster:hosts\n127.0.0.1 bar\n# END decster:hosts\nb\n"
+        );
+        assert_eq!(markers.remove(&updated).as_deref(), Some("a\nb\n"));
+
+        let other = BlockMarkers::new("other", "#");
+        assert_eq!(other.contents(&text), None);
+        assert_eq!(other.remove(&text), None);
+        assert_eq!(
+            markers.insert("", ""),
+            "# BEGIN decster:hosts\n# END decster:hosts\n"
+        );
+    }
+}
@@ -0,0 +1,52 @@
+use std::{env, ffi::OsStr, fs, path::Path};
+
+use anyhow::{Context, Result, bail};
+use serde::Deserialize;
+use toml::{Table, Value};
+
+use crate::{env::Env, utils::pretty::Pretty};
+
+/// A context variable that's read from an environment variable.
+#[derive(Deserialize)]
+#[serde(untagged, deny_unknown_fields)]
+pub enum ContextEnv {
+    Var(String),
+    #[serde(rename_all = "kebab-case")]
+    VarWithDefault {
+        var: String,
+        default: Option<Value>,
+    },
+}
+
+impl ContextEnv {
+    pub fn value(&self, name: &str) -> Result<Option<Value>> {
+        let (var, default) = match self {
+            ContextEnv::Var(var) => (var, None),
+            ContextEnv::VarWithDefault { var, default } => (var, default.as_ref()),
+        };
+        Ok(match env::var(var) {
+            Ok(value) => Some(Value::String(value)),
+            Err(env::VarError::NotPresent) => default.cloned(),
+            Err(err) => bail!("Couldn't read environment variable `{var}` for `{name}` ({err})"),
+        })
+    }
+}
+
+/// Loads a TOML, JSON or YAML file as a context. Relative paths are relative to
+/// the config directory.
+pub fn load_file(env: &mut Env, path: &Path) -> Result<Table> {
+    let path = env.untildefy(path)?.into_owned();
+    let path = env.config_dir().join(path);
+    parse_file(&path)
+        .with_context(|| format!("Couldn't load context from {}", env.tildefy(&path).pretty()))
+}
+
+fn parse_file(path: &Path) -> Result<Table> {
+    let string = fs::read_to_string(path)?;
+    Ok(match path.extension().and_then(OsStr::to_str) {
+        Some("toml") => toml::from_str(&string)?,
+        Some("json") => serde_json::from_str(&string)?,
+        Some("yaml" | "yml") => serde_norway::from_str(&string)?,
+        _ => bail!("Context files must be TOML, JSON or YAML files"),
+    })
+}
@@ -0,0 +1,60 @@
+use std::{
+    collections::BTreeSet,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Result, bail};
+use serde::Deserialize;
+
+use crate::{
+    env::Env,
+    fs::{mode::Mode, owner::Owner},
+    module::link::{LinkPermissions, ModuleLink},
+    state::{
+        State,
+        path::{PathInfo, PathPermissions},
+    },
+};
+
+/// A `directories` entry, which creates a directory without a source. The
+/// module's owner and directory mode are used if the entry doesn't have its
+/// own.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+pub struct DirectoryEntry {
+    #[serde(default)]
+    pub owner: Option<Owner>,
+    #[serde(default)]
+    pub mode: Option<Mode>,
+}
+
+/// Creates the directory at `path` and its missing parents. Parents are given
+/// the owner and directory mode of `permissions`, like the parents of links,
+/// while the directory itself is given its owner and mode even if it already
+/// existed. Directories that already existed aren't owned by the module, so
+/// they're kept when it's disabled.
+pub fn create(
+    env: &Env,
+    state: &mut State,
+    module: &str,
+    path: &Path,
+    permissions: &LinkPermissions,
+    left_dirs: &mut BTreeSet<PathBuf>,
+) -> Result<()> {
+    if state.is_path_owned(path) {
+        bail!("Path is used by another module");
+    } else if path.exists() && !path.is_dir() {
+        bail!("Path exists and isn't a directory");
+    }
+    ModuleLink::create_path(env, state, module, path, permissions, left_dirs)?;
+    if !ModuleLink::create_dir(state, module, path, left_dirs)? {
+        let original = PathPermissions::from_path(path)?;
+        let info = PathInfo::ExistingDirectory {
+            original,
+            permissions: None,
+        };
+        state.add_shared_path(module, path, info);
+    }
+    permissions.set(env, path)?;
+    state.record_permissions(module, path)
+}
@@ -0,0 +1,56 @@
+use serde::Deserialize;
+
+use crate::fs::{mode::Mode, owner::Owner};
+
+use super::source::ModuleSource;
+
+/// A `files`, `hard-links` or `symlinks` entry, which is either a source or a
+/// table containing a source and permissions overriding the module's.
+#[derive(Deserialize)]
+#[serde(from = "LinkEntryDef")]
+pub struct LinkEntry {
+    pub source: ModuleSource,
+    pub permissions: EntryPermissions,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged, deny_unknown_fields)]
+enum LinkEntryDef {
+    Table {
+        source: ModuleSource,
+        #[serde(flatten)]
+        permissions: EntryPermissions,
+    },
+    Source(ModuleSource),
+}
+
+impl From<LinkEntryDef> for LinkEntry {
+    fn from(value: LinkEntryDef) -> Self {
+        match value {
+            LinkEntryDef::Table {
+                source,
+                permissions,
+            } => LinkEntry {
+                source,
+                permissions,
+            },
+            LinkEntryDef::Source(source) => LinkEntry {
+                source,
+                permissions: EntryPermissions::default(),
+            },
+        }
+    }
+}
+
+/// The owner and mode of an entry's paths, and the mode of directories created
+/// for it. Unset values fall back to the module's.
+#[derive(Default, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+pub struct EntryPermissions {
+    #[serde(default)]
+    pub owner: Option<Owner>,
+    #[serde(default)]
+    pub mode: Option<Mode>,
+    #[serde(default)]
+    pub dir_mode: Option<Mode>,
+}
@@ -0,0 +1,141 @@
+use std::{ffi::OsString, path::Path, process::Command};
+
+use anyhow::{Context, Result, bail};
+use derive_more::Display;
+use serde::Deserialize;
+
+use crate::{config, env::Env};
+
+#[derive(Clone, Copy, Display)]
+pub enum HookKind {
+    #[display("pre-enable")]
+    PreEnable,
+    #[display("post-enable")]
+    PostEnable,
+    #[display("pre-disable")]
+    PreDisable,
+    #[display("post-disable")]
+    PostDisable,
+    #[display("on-change")]
+    OnChange,
+}
+
+/// Commands run when a module is enabled, disabled or changes paths.
+#[derive(Default, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Hooks {
+    #[serde(default)]
+    pre_enable: Option<Hook>,
+    #[serde(default)]
+    post_enable: Option<Hook>,
+    #[serde(default)]
+    pre_disable: Option<Hook>,
+    #[serde(default)]
+    post_disable: Option<Hook>,
+    #[serde(default)]
+    on_change: Option<Hook>,
+}
+
+impl Hooks {
+    pub fn get(&self, kind: HookKind) -> Option<&Hook> {
+        match kind {
+            HookKind::PreEnable => self.pre_enable.as_ref(),
+            HookKind::PostEnable => self.post_enable.as_ref(),
+            HookKind::PreDisable => self.pre_disable.as_ref(),
+            HookKind::PostDisable => self.post_disable.as_ref(),
+            HookKind::OnChange => self.on_change.as_ref(),
+        }
+    }
+}
+
+/// A command that's either a string run with `sh -c` or a list of arguments.
+/// The table form allows running the command with `root-command`.
+#[derive(Deserialize)]
+#[serde(try_from = "HookDef")]
+pub struct Hook {
+    command: Vec<String>,
+    root: bool,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged, deny_unknown_fields)]
+enum HookDef {
+    Command(HookCommand),
+    #[serde(rename_all = "kebab-case")]
+    Table {
+        command: HookCommand,
+        #[serde(default)]
+        root: bool,
+    },
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum HookCommand {
+    Shell(String),
+    Args(Vec<String>),
+}
+
+impl TryFrom<HookDef> for Hook {
+    type Error = &'static str;
+
+    fn try_from(value: HookDef) -> Result<Self, Self::Error> {
+        let (command, root) = match value {
+            HookDef::Command(command) => (command, false),
+            HookDef::Table { command, root } => (command, root),
+        };
+        let command = match command {
+            HookCommand::Shell(command) => vec!["sh".to_string(), "-c".to_string(), command],
+            HookCommand::Args(args) if args.is_empty() => return Err("Hook command is empty"),
+            HookCommand::Args(args) => args,
+        };
+        Ok(Hook { command, root })
+    }
+}
+
+impl Hook {
+    /// Runs the command in the config directory. The name of the module is
+    /// passed in `DECSTER_MODULE` and the changed paths are passed in
+    /// `DECSTER_CHANGED_PATHS`, separated by newlines.
+    pub fn run(&self, env: &Env, kind: HookKind, module: &str, paths: &[&Path]) -> Result<()> {
+        let mut changed_paths = OsString::new();
+        for (i, path) in paths.iter().enumerate() {
+            if i > 0 {
+                changed_paths.push("\n");
+            }
+            changed_paths.push(path);
+        }
+        let vars = [
+            ("DECSTER_MODULE", OsString::from(module)),
+            ("DECSTER_CHANGED_PATHS", changed_paths),
+        ];
+
+        let mut command = match self.root.then(config::root_command).flatten() {
+            // Root commands like `sudo` usually reset the environment, so
+            // variables are set with `env` instead.
+            Some(mut command) => {
+                command.arg("env");
+                for (name, value) in vars {
+                    let mut var = OsString::from(format!("{name}="));
+                    var.push(value);
+                    command.arg(var);
+                }
+                command.args(&self.command);
+                command
+            }
+            None => {
+                let mut command = Command::new(&self.command[0]);
+                command.args(&self.command[1..]).envs(vars);
+                command
+            }
+        };
+        let status = command
+            .current_dir(env.config_dir())
+            .status()
+            .with_context(|| format!("Couldn't run `{kind}` hook"))?;
+        if !status.success() {
+            bail!("Hook `{kind}` failed ({status})");
+        }
+        Ok(())
+    }
+}
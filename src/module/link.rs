@@ -1,6 +1,6 @@
 use std::{
     borrow::Cow,
-    collections::HashMap,
+    collections::BTreeSet,
     fs::{self, File},
     io::{self, Write},
     os::unix::{self, fs::MetadataExt},
@@ -11,7 +11,7 @@ use anyhow::{Context, Result, bail};
 use clap::ArgMatches;
 use crossterm::style::Stylize;
 use derive_more::Display;
-use toml::Value;
+use toml::Table;
 
 use crate::{
     env::Env,
@@ -24,7 +24,11 @@ use crate::{
     utils::{pretty::Pretty, sha256::Sha256Hash},
 };
 
-use super::source::ModuleSource;
+use super::{
+    block::{self, BlockMarkers},
+    source::ModuleSource,
+    template::TemplateGlobs,
+};
 
 #[derive(Clone, Copy, Display, Eq, Ord, PartialEq, PartialOrd)]
 pub enum LinkKind {
@@ -36,6 +40,8 @@ pub enum LinkKind {
     Symlink,
     #[display("{}", "Template".blue())]
     Template,
+    #[display("{}", "Block".blue())]
+    Block,
 }
 
 #[derive(Clone, Copy)]
@@ -59,44 +65,99 @@ impl LinkMethod {
     }
 }
 
-#[derive(Display, Eq, Ord, PartialOrd)]
+/// The owner and mode of a link's paths, and the mode of directories created
+/// for it.
+#[derive(Clone, Copy, Default)]
+pub struct LinkPermissions {
+    pub owner: Option<OwnerIds>,
+    pub mode: Option<Mode>,
+    pub dir_mode: Option<Mode>,
+}
+
+impl LinkPermissions {
+    pub(crate) fn set(&self, env: &Env, path: &Path) -> Result<()> {
+        if let Some(owner) = &self.owner {
+            owner.set(env, path)?;
+        }
+        if let Some(mode) = &self.mode {
+            mode.set(env, path)?;
+        }
+        Ok(())
+    }
+
+    fn set_dir(&self, env: &Env, path: &Path) -> Result<()> {
+        if let Some(owner) = &self.owner {
+            owner.set(env, path)?;
+        }
+        if let Some(mode) = &self.dir_mode {
+            mode.set(env, path)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Display)]
 #[display("{} -> {source}", path.pretty())]
 pub struct ModuleLink<'a> {
     kind: LinkKind,
-    path: &'a Path,
-    source: &'a ModuleSource,
-    owner: Option<OwnerIds>,
-    mode: Option<Mode>,
+    path: Cow<'a, Path>,
+    source: Cow<'a, ModuleSource>,
+    permissions: LinkPermissions,
+    template_globs: Option<&'a TemplateGlobs>,
+    block_comment: Option<&'a str>,
 }
 
 impl<'a> ModuleLink<'a> {
     pub fn new(
         kind: LinkKind,
-        path: &'a Path,
-        source: &'a ModuleSource,
-        owner: Option<OwnerIds>,
-        mode: Option<Mode>,
+        path: Cow<'a, Path>,
+        source: Cow<'a, ModuleSource>,
+        permissions: LinkPermissions,
     ) -> Self {
         ModuleLink {
             kind,
             path,
             source,
-            owner,
-            mode,
+            permissions,
+            template_globs: None,
+            block_comment: None,
         }
     }
 
+    pub fn with_template_globs(mut self, globs: &'a TemplateGlobs) -> Self {
+        self.template_globs = Some(globs);
+        self
+    }
+
+    pub fn with_block_comment(mut self, comment: &'a str) -> Self {
+        self.block_comment = Some(comment);
+        self
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
     pub fn create(
         &self,
         env: &mut Env,
         state: &mut State,
         module: &str,
-        context: &HashMap<&str, &Value>,
+        context: &Table,
         method: LinkMethod,
+        left_dirs: &mut BTreeSet<PathBuf>,
     ) -> Result<()> {
-        let source_path = self.source.fetch(env, state, module, self.path)?;
-        let link_path = env.untildefy(self.path)?;
-        self.create_path(env, state, module, &link_path)?;
+        let source_path = self.source.fetch(env, state, module, &self.path)?;
+        let link_path = env.untildefy(&self.path)?;
+        Self::create_path(env, state, module, &link_path, &self.permissions, left_dirs)?;
+        if self.kind == LinkKind::Block {
+            return self
+                .create_block(env, state, module, &source_path, &link_path)
+                .with_context(|| {
+                    let link_path = env.tildefy(&link_path);
+                    format!("Couldn't create {} ({})", link_path.pretty(), self.kind)
+                });
+        }
 
         crate::fs::walk_dir_rel(source_path, false, false, |path, rel_path| {
             let mut new_path = Cow::Borrowed(link_path.as_ref());
@@ -107,21 +168,27 @@ impl<'a> ModuleLink<'a> {
             if state.is_path_owned(&new_path) {
                 bail!("Path is used by another module");
             } else if path.is_dir() {
-                if Self::create_dir(state, module, &new_path)? {
-                    self.set_or_copy_permissions(env, path, &new_path)?;
+                if Self::create_dir(state, module, &new_path, left_dirs)? {
+                    self.set_or_copy_dir_permissions(env, path, &new_path)?;
+                    state.record_permissions(module, &new_path)?;
                 }
             } else if let Some(info) = match self.kind {
                 LinkKind::File => Self::create_file(method, path, &new_path),
                 LinkKind::HardLink => Self::create_hard_link(method, path, &new_path),
                 LinkKind::Symlink => Self::create_symlink(method, path, &new_path),
+                LinkKind::Template if !self.is_template(rel_path) => {
+                    Self::create_file(method, path, &new_path)
+                }
                 LinkKind::Template => Self::create_template(method, path, &new_path, context),
+                LinkKind::Block => unreachable!("Blocks should be created by `create_block`"),
             }
             .with_context(|| {
                 let new_path = env.tildefy(new_path.as_ref());
                 format!("Couldn't create {} ({})", new_path.pretty(), self.kind)
             })? {
                 state.add_path(module, &new_path, info);
-                self.set_permissions(env, &new_path)?;
+                self.permissions.set(env, &new_path)?;
+                state.record_permissions(module, &new_path)?;
             }
 
             Ok(())
@@ -129,24 +196,81 @@ impl<'a> ModuleLink<'a> {
         Ok(())
     }
 
-    fn create_dir(state: &mut State, module: &str, path: &Path) -> Result<bool> {
-        Ok(if !path.is_dir() {
+    /// Renders the template at `path`, which is either the link's path or a
+    /// path inside of it, without creating or fetching anything.
+    pub fn render(
+        &self,
+        env: &mut Env,
+        state: &State,
+        module: &str,
+        path: &Path,
+        context: &Table,
+    ) -> Result<String> {
+        if self.kind != LinkKind::Template {
+            bail!("{} isn't a template ({})", self.path.pretty(), self.kind);
+        }
+        let link_path = env.untildefy(&self.path)?.into_owned();
+        let rel_path = path
+            .strip_prefix(&link_path)
+            .expect("`path` should be inside of the link's path");
+        let mut source_path = self.source.fetched_path(env, state, module, &self.path)?;
+        if rel_path.parent().is_some() {
+            source_path.push(rel_path);
+        }
+        let template = fs::read_to_string(&source_path)
+            .with_context(|| format!("Couldn't read {}", env.tildefy(&source_path).pretty()))?;
+        Ok(match self.is_template(rel_path) {
+            true => upon::render_source(&template, context)?,
+            false => template,
+        })
+    }
+
+    /// Returns whether a file in a templated source should be rendered. The root
+    /// of the source is always rendered.
+    fn is_template(&self, rel_path: &Path) -> bool {
+        rel_path.parent().is_none()
+            || self
+                .template_globs
+                .is_none_or(|globs| globs.is_template(rel_path))
+    }
+
+    /// Creates a directory if it doesn't exist, or takes over a directory in
+    /// `left_dirs`, which the module left behind while it was being updated.
+    /// Returns whether the module owns the directory, in which case its
+    /// permissions should be set.
+    pub(crate) fn create_dir(
+        state: &mut State,
+        module: &str,
+        path: &Path,
+        left_dirs: &mut BTreeSet<PathBuf>,
+    ) -> Result<bool> {
+        if !path.is_dir() {
             fs::create_dir(path)
                 .with_context(|| format!("Couldn't create directory: {}", path.pretty()))?;
-            state.add_path(module, path, PathInfo::Directory);
-            true
-        } else {
-            false
-        })
+        } else if !left_dirs.remove(path) {
+            return Ok(false);
+        }
+        state.add_path(module, path, PathInfo::Directory { permissions: None });
+        Ok(true)
     }
 
-    fn create_path(&self, env: &Env, state: &mut State, module: &str, path: &Path) -> Result<()> {
+    /// Creates the missing parents of `path`, which are given the owner and
+    /// directory mode of `permissions`.
+    pub(crate) fn create_path(
+        env: &Env,
+        state: &mut State,
+        module: &str,
+        path: &Path,
+        permissions: &LinkPermissions,
+        left_dirs: &mut BTreeSet<PathBuf>,
+    ) -> Result<()> {
         let mut components = PathBuf::from("");
         if let Some(parent) = path.parent() {
             for component in parent.components() {
                 components.push(component);
-                if Self::create_dir(state, module, &components)? {
-                    self.set_permissions(env, &components)?;
+                if Self::create_dir(state, module, &components, left_dirs)? {
+                    permissions.set_dir(env, &components)?;
+                    state.record_permissions(module, &components)?;
                 }
             }
         }
@@ -156,7 +280,11 @@ impl<'a> ModuleLink<'a> {
     fn create_file(method: LinkMethod, from: &Path, to: &Path) -> Result<Option<PathInfo>> {
         let size = from.symlink_metadata()?.size();
         let hash = Sha256Hash::from_file(from)?;
-        let info = PathInfo::File { size, hash };
+        let info = PathInfo::File {
+            size,
+            hash,
+            permissions: None,
+        };
         let got_path =
             Self::create_with_method(to, &info, method, || Ok(crate::fs::copy(from, to)?))?;
         Ok(got_path.then_some(info))
@@ -169,7 +297,11 @@ impl<'a> ModuleLink<'a> {
     ) -> Result<Option<PathInfo>> {
         let size = original.symlink_metadata()?.size();
         let hash = Sha256Hash::from_file(original)?;
-        let info = PathInfo::HardLink { size, hash };
+        let info = PathInfo::HardLink {
+            size,
+            hash,
+            permissions: None,
+        };
         let got_path =
             Self::create_with_method(link, &info, method, || Ok(fs::hard_link(original, link)?))?;
         Ok(got_path.then_some(info))
@@ -193,19 +325,55 @@ impl<'a> ModuleLink<'a> {
         method: LinkMethod,
         from: &Path,
         to: &Path,
-        context: &HashMap<&str, &Value>,
+        context: &Table,
     ) -> Result<Option<PathInfo>> {
         let template = fs::read_to_string(from)?;
-        let render = upon::render(&template, context)?;
+        let render = upon::render_source(&template, context)?;
         let size = render.len() as u64;
         let hash = Sha256Hash::from_bytes(&render);
-        let info = PathInfo::File { size, hash };
+        let info = PathInfo::File {
+            size,
+            hash,
+            permissions: None,
+        };
         let got_path = Self::create_with_method(to, &info, method, || {
             Ok(File::create_new(to)?.write_all(render.as_bytes())?)
         })?;
         Ok(got_path.then_some(info))
     }
 
+    /// Inserts the block into the file at `path`, replacing an older version
+    /// of it. The file is created if it doesn't exist.
+    fn create_block(
+        &self,
+        env: &Env,
+        state: &mut State,
+        module: &str,
+        source_path: &Path,
+        path: &Path,
+    ) -> Result<()> {
+        if state.is_path_owned(path) {
+            bail!("Path is used by another module");
+        } else if !source_path.is_file() {
+            bail!("Sources of blocks must be files");
+        }
+        let contents = fs::read_to_string(source_path)?;
+        let contents = block::normalize(&contents);
+        let markers = BlockMarkers::new(module, self.block_comment.unwrap_or("#"));
+        let exists = path.exists();
+        let text = match exists {
+            true => fs::read_to_string(path)?,
+            false => String::new(),
+        };
+        fs::write(path, markers.insert(&text, &contents))?;
+        if !exists {
+            self.permissions.set(env, path)?;
+        }
+        let hash = Sha256Hash::from_bytes(contents.as_bytes());
+        state.add_shared_path(module, path, PathInfo::Block { markers, hash });
+        Ok(())
+    }
+
     fn create_with_method<F>(path: &Path, info: &PathInfo, method: LinkMethod, f: F) -> Result<bool>
     where
         F: Fn() -> Result<()>,
@@ -235,30 +403,18 @@ impl<'a> ModuleLink<'a> {
         })
     }
 
-    fn set_permissions(&self, env: &Env, path: &Path) -> Result<()> {
-        if let Some(owner) = &self.owner {
-            owner.set(env, path)?;
-        }
-        if let Some(mode) = &self.mode {
-            mode.set(env, path)?;
-        }
-        Ok(())
-    }
-
-    fn set_or_copy_permissions(&self, env: &Env, from: &Path, to: &Path) -> Result<()> {
+    fn set_or_copy_dir_permissions(&self, env: &Env, from: &Path, to: &Path) -> Result<()> {
         let md = from
             .symlink_metadata()
             .with_context(|| format!("Couldn't read metadata of {}", from.pretty()))?;
-        self.owner
+        self.permissions
+            .owner
             .unwrap_or(OwnerIds::from_metadata(&md))
             .set(env, to)?;
-        self.mode.unwrap_or(Mode::from_metadata(&md)).set(env, to)?;
+        self.permissions
+            .dir_mode
+            .unwrap_or(Mode::from_metadata(&md))
+            .set(env, to)?;
         Ok(())
     }
 }
-
-impl PartialEq for ModuleLink<'_> {
-    fn eq(&self, other: &Self) -> bool {
-        self.path == other.path
-    }
-}
@@ -0,0 +1,620 @@
+use std::{
+    ffi::OsStr,
+    fmt::{self, Display, Formatter},
+    fs,
+    ops::Range,
+    path::Path,
+};
+
+use anyhow::{Context, Result, bail};
+use jsonc_parser::{
+    ParseOptions,
+    cst::{CstInputValue, CstObject, CstRootNode},
+};
+use serde::Deserialize;
+use serde_json::{Map, Value as JsonValue};
+use toml::{Table, Value, de::ValueDeserializer};
+use toml_edit::{DocumentMut, Item, TableLike};
+
+/// Format of a file that keys are merged into, which is detected from its
+/// extension.
+#[derive(Clone, Copy)]
+enum Format {
+    Toml,
+    Json,
+    Yaml,
+    Ini,
+}
+
+impl Format {
+    fn from_path(path: &Path) -> Result<Self> {
+        Ok(match path.extension().and_then(OsStr::to_str) {
+            Some("toml") => Format::Toml,
+            Some("json") => Format::Json,
+            Some("yaml" | "yml") => Format::Yaml,
+            Some("ini") => Format::Ini,
+            _ => bail!("Merged files must be TOML, JSON, YAML or INI files"),
+        })
+    }
+}
+
+/// A parsed file. TOML and JSON files are edited in place, so comments and
+/// formatting are preserved. JSON files may contain comments and trailing
+/// commas.
+enum Document {
+    Toml(DocumentMut),
+    Json(CstRootNode),
+    /// YAML files are rewritten as a whole, so comments and formatting aren't
+    /// preserved.
+    Yaml(Map<String, JsonValue>),
+    Ini(Ini),
+}
+
+impl Document {
+    /// Reads a file, which is treated as empty if it doesn't exist.
+    fn read(path: &Path) -> Result<Self> {
+        let format = Format::from_path(path)?;
+        let text = match path.exists() {
+            true => fs::read_to_string(path)?,
+            false => String::new(),
+        };
+        Document::parse(format, &text)
+    }
+
+    fn parse(format: Format, text: &str) -> Result<Self> {
+        Ok(match format {
+            Format::Toml => Document::Toml(text.parse()?),
+            Format::Json => Document::Json(CstRootNode::parse(text, &ParseOptions::default())?),
+            Format::Yaml if text.trim().is_empty() => Document::Yaml(Map::new()),
+            Format::Yaml => Document::Yaml(serde_norway::from_str(text)?),
+            Format::Ini => Document::Ini(Ini::parse(text)),
+        })
+    }
+
+    fn write(&self, path: &Path) -> Result<()> {
+        Ok(fs::write(path, self.to_string())?)
+    }
+
+    fn set(&mut self, key: &[&str], value: &Value) -> Result<()> {
+        let (last, parents) = key.split_last().unwrap();
+        match self {
+            Document::Toml(document) => {
+                let mut table = document.as_table_mut() as &mut dyn TableLike;
+                for parent in parents {
+                    let item = table.entry(parent).or_insert_with(implicit_table);
+                    if !item.is_table_like() {
+                        *item = implicit_table();
+                    }
+                    table = item.as_table_like_mut().unwrap();
+                }
+                let mut value = value.to_string().parse::<toml_edit::Value>()?;
+                // Existing values are replaced in place to keep the comments
+                // around them.
+                match table.get_mut(last).and_then(Item::as_value_mut) {
+                    Some(current) => {
+                        *value.decor_mut() = current.decor().clone();
+                        *current = value;
+                    }
+                    None => {
+                        table.insert(last, Item::Value(value));
+                    }
+                }
+            }
+            Document::Json(root) => {
+                let mut object = root.object_value_or_set();
+                for parent in parents {
+                    object = object.object_value_or_set(parent);
+                }
+                let value = json_input(serde_json::to_value(value)?);
+                match object.get(last) {
+                    Some(property) => property.set_value(value),
+                    None => {
+                        object.append(last, value);
+                    }
+                }
+            }
+            Document::Yaml(map) => {
+                let mut map = map;
+                for parent in parents {
+                    let value = map
+                        .entry(parent.to_string())
+                        .or_insert_with(|| JsonValue::Object(Map::new()));
+                    if !value.is_object() {
+                        *value = JsonValue::Object(Map::new());
+                    }
+                    map = value.as_object_mut().unwrap();
+                }
+                map.insert(last.to_string(), serde_json::to_value(value)?);
+            }
+            Document::Ini(ini) => {
+                let (section, key) = Ini::key(key)?;
+                ini.set(section, key, &Ini::value(value));
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the value of a key. Values that can't be represented in TOML,
+    /// like JSON's `null`, are treated as missing.
+    fn get(&self, key: &[&str]) -> Option<Value> {
+        let (last, parents) = key.split_last().unwrap();
+        match self {
+            Document::Toml(document) => {
+                let mut table = document.as_table() as &dyn TableLike;
+                for parent in parents {
+                    table = table.get(parent)?.as_table_like()?;
+                }
+                let mut value = table.get(last)?.clone().into_value().ok()?;
+                value.decor_mut().clear();
+                let value = value.to_string();
+                Value::deserialize(ValueDeserializer::new(&value)).ok()
+            }
+            Document::Json(root) => {
+                let mut object = root.object_value()?;
+                for parent in parents {
+                    object = object.object_value(parent)?;
+                }
+                let value = object.get(last)?.value()?.to_serde_value()?;
+                Value::try_from(value).ok()
+            }
+            Document::Yaml(map) => {
+                let mut map = map;
+                for parent in parents {
+                    map = map.get(*parent)?.as_object()?;
+                }
+                Value::try_from(map.get(*last)?).ok()
+            }
+            Document::Ini(ini) => {
+                let (section, key) = Ini::key(key).ok()?;
+                Some(Value::String(ini.get(section, key)?.to_string()))
+            }
+        }
+    }
+
+    fn matches(&self, key: &[&str], value: &Value) -> bool {
+        match self {
+            Document::Ini(ini) => Ini::key(key)
+                .is_ok_and(|(section, key)| ini.get(section, key) == Some(&Ini::value(value))),
+            _ => self.get(key).as_ref() == Some(value),
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        match self {
+            Document::Toml(document) => document.is_empty(),
+            Document::Json(root) => root
+                .object_value()
+                .is_none_or(|object| object.properties().is_empty()),
+            Document::Yaml(map) => map.is_empty(),
+            Document::Ini(ini) => ini.lines.iter().all(|line| line.trim().is_empty()),
+        }
+    }
+
+    /// Removes a key, along with tables that become empty.
+    fn remove(&mut self, key: &[&str]) {
+        match self {
+            Document::Toml(document) => remove_toml(document.as_table_mut(), key),
+            Document::Json(root) => {
+                if let Some(object) = root.object_value() {
+                    remove_json(&object, key);
+                }
+            }
+            Document::Yaml(map) => remove_yaml(map, key),
+            Document::Ini(ini) => {
+                if let Ok((section, key)) = Ini::key(key) {
+                    ini.remove(section, key);
+                }
+            }
+        }
+    }
+}
+
+impl Display for Document {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Document::Toml(document) => write!(f, "{document}"),
+            Document::Json(root) => {
+                let text = root.to_string();
+                match text.ends_with('\n') {
+                    true => write!(f, "{text}"),
+                    false => writeln!(f, "{text}"),
+                }
+            }
+            Document::Yaml(map) => {
+                let text = serde_norway::to_string(map).map_err(|_| fmt::Error)?;
+                write!(f, "{text}")
+            }
+            Document::Ini(ini) => write!(f, "{ini}"),
+        }
+    }
+}
+
+/// Returns a table that only gets a header if it contains values of its own.
+fn implicit_table() -> Item {
+    let mut table = toml_edit::Table::new();
+    table.set_implicit(true);
+    Item::Table(table)
+}
+
+fn json_input(value: JsonValue) -> CstInputValue {
+    match value {
+        JsonValue::Null => CstInputValue::Null,
+        JsonValue::Bool(bool) => CstInputValue::Bool(bool),
+        JsonValue::Number(number) => CstInputValue::Number(number.to_string()),
+        JsonValue::String(string) => CstInputValue::String(string),
+        JsonValue::Array(values) => {
+            CstInputValue::Array(values.into_iter().map(json_input).collect())
+        }
+        JsonValue::Object(map) => CstInputValue::Object(
+            map.into_iter()
+                .map(|(key, value)| (key, json_input(value)))
+                .collect(),
+        ),
+    }
+}
+
+fn remove_toml(table: &mut dyn TableLike, key: &[&str]) {
+    match key {
+        [] => (),
+        [last] => {
+            table.remove(last);
+        }
+        [parent, rest @ ..] => {
+            if let Some(child) = table.get_mut(parent).and_then(Item::as_table_like_mut) {
+                remove_toml(child, rest);
+                if child.is_empty() {
+                    table.remove(parent);
+                }
+            }
+        }
+    }
+}
+
+fn remove_json(object: &CstObject, key: &[&str]) {
+    match key {
+        [] => (),
+        [last] => {
+            if let Some(property) = object.get(last) {
+                property.remove();
+            }
+        }
+        [parent, rest @ ..] => {
+            if let Some(child) = object.object_value(parent) {
+                remove_json(&child, rest);
+                if child.properties().is_empty() {
+                    object.get(parent).unwrap().remove();
+                }
+            }
+        }
+    }
+}
+
+fn remove_yaml(map: &mut Map<String, JsonValue>, key: &[&str]) {
+    match key {
+        [] => (),
+        [last] => {
+            map.shift_remove(*last);
+        }
+        [parent, rest @ ..] => {
+            if let Some(JsonValue::Object(child)) = map.get_mut(*parent) {
+                remove_yaml(child, rest);
+                if child.is_empty() {
+                    map.shift_remove(*parent);
+                }
+            }
+        }
+    }
+}
+
+/// An INI file, which is edited line by line so comments and formatting are
+/// preserved. Keys before the first section are treated as top-level keys.
+struct Ini {
+    lines: Vec<String>,
+}
+
+impl Ini {
+    fn parse(text: &str) -> Self {
+        Ini {
+            lines: text.lines().map(str::to_string).collect(),
+        }
+    }
+
+    fn key<'a>(key: &[&'a str]) -> Result<(Option<&'a str>, &'a str)> {
+        match key {
+            [key] => Ok((None, key)),
+            [section, key] => Ok((Some(section), key)),
+            _ => bail!("INI files can only contain sections of keys"),
+        }
+    }
+
+    fn value(value: &Value) -> String {
+        match value {
+            Value::String(value) => value.clone(),
+            value => value.to_string(),
+        }
+    }
+
+    fn is_header(line: &str) -> bool {
+        let line = line.trim();
+        line.starts_with('[') && line.ends_with(']')
+    }
+
+    /// Returns the index of a section's header and the range of lines in the
+    /// section, excluding the header.
+    fn section(&self, section: Option<&str>) -> Option<(Option<usize>, Range<usize>)> {
+        let header = match section {
+            None => None,
+            Some(section) => Some(
+                self.lines
+                    .iter()
+                    .position(|line| line.trim() == format!("[{section}]"))?,
+            ),
+        };
+        let start = header.map_or(0, |header| header + 1);
+        let end = self.lines[start..]
+            .iter()
+            .position(|line| Self::is_header(line))
+            .map_or(self.lines.len(), |i| start + i);
+        Some((header, start..end))
+    }
+
+    fn find(&self, section: Option<&str>, key: &str) -> Option<usize> {
+        let (_, range) = self.section(section)?;
+        range.into_iter().find(|i| {
+            let line = self.lines[*i].trim();
+            !line.starts_with([';', '#'])
+                && line
+                    .split_once('=')
+                    .is_some_and(|(name, _)| name.trim() == key)
+        })
+    }
+
+    fn get(&self, section: Option<&str>, key: &str) -> Option<&str> {
+        let line = &self.lines[self.find(section, key)?];
+        line.split_once('=').map(|(_, value)| value.trim())
+    }
+
+    fn set(&mut self, section: Option<&str>, key: &str, value: &str) {
+        let line = format!("{key}={value}");
+        if let Some(i) = self.find(section, key) {
+            self.lines[i] = line;
+        } else if let Some((header, range)) = self.section(section) {
+            // New keys are inserted after the last non-empty line of the
+            // section.
+            let i = range
+                .rev()
+                .find(|i| !self.lines[*i].trim().is_empty())
+                .map_or(header.map_or(0, |header| header + 1), |i| i + 1);
+            self.lines.insert(i, line);
+        } else {
+            if self
+                .lines
+                .last()
+                .is_some_and(|line| !line.trim().is_empty())
+            {
+                self.lines.push(String::new());
+            }
+            self.lines.push(format!("[{}]", section.unwrap()));
+            self.lines.push(line);
+        }
+    }
+
+    /// Removes a key, along with its section if the section becomes empty.
+    fn remove(&mut self, section: Option<&str>, key: &str) {
+        let Some(i) = self.find(section, key) else {
+            return;
+        };
+        self.lines.remove(i);
+        if let Some((Some(header), range)) = self.section(section)
+            && self.lines[range.clone()]
+                .iter()
+                .all(|line| line.trim().is_empty())
+        {
+            self.lines.drain(header..range.end);
+            if header > 0 && self.lines[header - 1].trim().is_empty() {
+                self.lines.remove(header - 1);
+            }
+        }
+    }
+}
+
+impl Display for Ini {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        for line in self.lines.iter() {
+            writeln!(f, "{line}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Returns the keys of every value in `table` that isn't a table.
+fn leaves(table: &Table) -> Vec<(Vec<&str>, &Value)> {
+    let mut leaves = Vec::new();
+    leaves_inner(table, &mut Vec::new(), &mut leaves);
+    leaves
+}
+
+fn leaves_inner<'a>(
+    table: &'a Table,
+    key: &mut Vec<&'a str>,
+    leaves: &mut Vec<(Vec<&'a str>, &'a Value)>,
+) {
+    for (name, value) in table {
+        key.push(name);
+        match value {
+            Value::Table(table) => leaves_inner(table, key, leaves),
+            value => leaves.push((key.clone(), value)),
+        }
+        key.pop();
+    }
+}
+
+/// What a file contained before values were merged into it, which is restored
+/// when the values are removed again.
+pub struct Original {
+    /// Whether the file was created by merging.
+    pub created: bool,
+    /// Previous values of keys that were overwritten.
+    pub values: Table,
+}
+
+/// Merges `values` into the file at `path`, which is created if it doesn't
+/// exist.
+pub fn merge(path: &Path, values: &Table) -> Result<Original> {
+    let created = !path.exists();
+    let mut document = Document::read(path).context("Couldn't parse file")?;
+    let mut original = Table::new();
+    for (key, value) in leaves(values) {
+        if let Some(previous) = document.get(&key) {
+            insert(&mut original, &key, previous);
+        }
+        document.set(&key, value)?;
+    }
+    document.write(path)?;
+    Ok(Original {
+        created,
+        values: original,
+    })
+}
+
+/// Returns whether every value in `values` is still set in the file at `path`.
+pub fn is_merged(path: &Path, values: &Table) -> Result<bool> {
+    let document = Document::read(path)?;
+    Ok(leaves(values)
+        .into_iter()
+        .all(|(key, value)| document.matches(&key, value)))
+}
+
+/// Removes the keys in `values` from the file at `path`, unless their values
+/// have changed. Keys that were set before they were merged get their
+/// previous values back, and the file is removed if decster created it and
+/// it becomes empty. Returns whether every key was removed.
+pub fn unmerge(path: &Path, values: &Table, original: &Original) -> Result<bool> {
+    let mut document = Document::read(path).context("Couldn't parse file")?;
+    let mut removed_all = true;
+    for (key, value) in leaves(values) {
+        if !document.matches(&key, value) {
+            removed_all = false;
+        } else if let Some(previous) = get(&original.values, &key) {
+            document.set(&key, previous)?;
+        } else {
+            document.remove(&key);
+        }
+    }
+    match original.created && document.is_empty() {
+        true => fs::remove_file(path)?,
+        false => document.write(path)?,
+    }
+    Ok(removed_all)
+}
+
+/// Inserts `value` into nested tables of `table`, which are created if they
+/// don't exist.
+fn insert(table: &mut Table, key: &[&str], value: Value) {
+    let (last, parents) = key.split_last().unwrap();
+    let mut table = table;
+    for parent in parents {
+        table = table
+            .entry(parent.to_string())
+            .or_insert_with(|| Value::Table(Table::new()))
+            .as_table_mut()
+            .unwrap();
+    }
+    table.insert(last.to_string(), value);
+}
+
+fn get<'a>(table: &'a Table, key: &[&str]) -> Option<&'a Value> {
+    let (last, parents) = key.split_last().unwrap();
+    let mut table = table;
+    for parent in parents {
+        table = table.get(*parent)?.as_table()?;
+    }
+    table.get(*last)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use toml::toml;
+
+    #[test]
+    fn merge_ini() {
+        let text = "; comment\nroot=1\n\n[a]\nx = 1\n\n[b]\ny=2\n";
+        let mut document = Document::Ini(Ini::parse(text));
+        let values = toml! {
+            root = 2
+            new = "value"
+            [a]
+            z = true
+            [c]
+            w = 3
+        };
+        for (key, value) in leaves(&values) {
+            document.set(&key, value).unwrap();
+        }
+        let Document::Ini(ini) = &document else {
+            unreachable!()
+        };
+        assert_eq!(
+            ini.to_string(),
+            "; comment\nroot=2\nnew=value\n\n[a]\nx = 1\nz=true\n\n[b]\ny=2\n\n[c]\nw=3\n"
+        );
+        for (key, value) in leaves(&values) {
+            assert!(document.matches(&key, value));
+            document.remove(&key);
+        }
+        let Document::Ini(ini) = &document else {
+            unreachable!()
+        };
+        assert_eq!(ini.to_string(), "; comment\n\n[a]\nx = 1\n\n[b]\ny=2\n");
+    }
+
+    #[test]
+    fn merge_json() {
+        let text = "{\n  // comment\n  \"a\": {\n    \"b\": 1,\n  },\n  \"c\": null,\n}\n";
+        let mut document = Document::parse(Format::Json, text).unwrap();
+        let values = toml! {
+            a.d = "x"
+            e.f = [1, 2]
+        };
+        for (key, value) in leaves(&values) {
+            document.set(&key, value).unwrap();
+            assert!(document.matches(&key, value));
+        }
+        for (key, _) in leaves(&values) {
+            document.remove(&key);
+        }
+        assert_eq!(document.to_string(), text);
+    }
+
+    #[test]
+    fn merge_toml() {
+        let text = "# comment\nroot = 1 # root\n\n[a]\nx = \"y\"\n";
+        let mut document = Document::parse(Format::Toml, text).unwrap();
+        let values = toml! {
+            root = 2
+            a.z = true
+            b.c.d = 3
+        };
+        let mut previous = Table::new();
+        for (key, value) in leaves(&values) {
+            if let Some(value) = document.get(&key) {
+                insert(&mut previous, &key, value);
+            }
+            document.set(&key, value).unwrap();
+            assert!(document.matches(&key, value));
+        }
+        assert_eq!(previous, toml! { root = 1 });
+        assert_eq!(
+            document.to_string(),
+            "# comment\nroot = 2 # root\n\n[a]\nx = \"y\"\nz = true\n\n[b.c]\nd = 3\n"
+        );
+        for (key, _) in leaves(&values) {
+            match get(&previous, &key) {
+                Some(value) => document.set(&key, value).unwrap(),
+                None => document.remove(&key),
+            }
+        }
+        assert_eq!(document.to_string(), text);
+    }
+}
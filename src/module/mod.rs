@@ -1,26 +1,45 @@
 use std::{
-    collections::{BTreeMap, BTreeSet, HashMap},
+    collections::{BTreeMap, BTreeSet, HashSet},
     fs,
     path::{Path, PathBuf},
 };
 
+use absent::AbsentEntry;
 use anyhow::Result;
+use block::BlockEntry;
+use context::ContextEnv;
+use directory::DirectoryEntry;
+use entry::{EntryPermissions, LinkEntry};
+use hooks::Hooks;
 use indexmap::IndexMap;
+use link::LinkPermissions;
 use serde::Deserialize;
 use set::ModuleSet;
-use source::ModuleSource;
-use toml::Value;
+use template::TemplateEntry;
+use toml::Table;
 
 use crate::{
+    accounts::{Group, User},
     config,
+    env::Env,
     fs::{mode::Mode, owner::Owner},
     globs::Globs,
-    packages::PackageManager,
+    packages::{Package, PackageManager},
+    services::Service,
+    utils::merge::merge_tables,
 };
 
+pub mod absent;
+pub mod block;
+pub mod context;
+pub mod directory;
+pub mod entry;
+pub mod hooks;
 pub mod link;
+pub mod merge;
 pub mod set;
 pub mod source;
+pub mod template;
 
 #[derive(Deserialize)]
 #[serde(deny_unknown_fields, rename_all = "kebab-case")]
@@ -32,21 +51,46 @@ pub struct Module {
     owner: Option<Owner>,
     #[serde(default)]
     mode: Option<Mode>,
+    #[serde(default)]
+    dir_mode: Option<Mode>,
 
     #[serde(default)]
-    files: BTreeMap<PathBuf, ModuleSource>,
+    directories: BTreeMap<PathBuf, DirectoryEntry>,
+    #[serde(default)]
+    files: BTreeMap<PathBuf, LinkEntry>,
+    #[serde(default)]
+    hard_links: BTreeMap<PathBuf, LinkEntry>,
+    #[serde(default)]
+    symlinks: BTreeMap<PathBuf, LinkEntry>,
     #[serde(default)]
-    hard_links: BTreeMap<PathBuf, ModuleSource>,
+    templates: BTreeMap<PathBuf, TemplateEntry>,
     #[serde(default)]
-    symlinks: BTreeMap<PathBuf, ModuleSource>,
+    blocks: BTreeMap<PathBuf, BlockEntry>,
     #[serde(default)]
-    templates: BTreeMap<PathBuf, ModuleSource>,
+    merges: BTreeMap<PathBuf, Table>,
+    #[serde(default)]
+    absent: Vec<AbsentEntry>,
 
     #[serde(default)]
-    context: HashMap<String, Value>,
+    context: Table,
+    #[serde(default)]
+    context_files: Vec<PathBuf>,
+    #[serde(default)]
+    context_env: BTreeMap<String, ContextEnv>,
 
     #[serde(default)]
-    packages: BTreeMap<PackageManager, BTreeSet<String>>,
+    packages: BTreeMap<PackageManager, BTreeSet<Package>>,
+
+    #[serde(default)]
+    services: BTreeMap<String, Service>,
+
+    #[serde(default)]
+    users: BTreeMap<String, User>,
+    #[serde(default)]
+    groups: BTreeMap<String, Group>,
+
+    #[serde(default)]
+    hooks: Hooks,
 }
 
 impl Module {
@@ -58,6 +102,22 @@ impl Module {
         Ok(toml::from_str(&fs::read_to_string(path)?)?)
     }
 
+    pub fn package_managers(&self) -> impl Iterator<Item = &PackageManager> {
+        self.packages.keys()
+    }
+
+    /// Returns the permissions of one of the module's entries. Values the entry
+    /// doesn't set are taken from the module, and directories fall back to the
+    /// module's mode if neither sets a directory mode.
+    fn permissions(&self, env: &mut Env, entry: &EntryPermissions) -> Result<LinkPermissions> {
+        let owner = entry.owner.as_ref().or(self.owner.as_ref());
+        Ok(LinkPermissions {
+            owner: owner.map(|o| o.ids(env)).transpose()?,
+            mode: entry.mode.or(self.mode),
+            dir_mode: entry.dir_mode.or(self.dir_mode).or(self.mode),
+        })
+    }
+
     pub fn import<'a>(&'a self, name: &'a str) -> Result<ModuleSet<'a>> {
         let mut modules = IndexMap::from([(name, self)]);
         Self::import_inner(&mut modules, &self.imports)?;
@@ -73,4 +133,39 @@ impl Module {
         }
         Ok(())
     }
+
+    /// Returns the module's context merged on top of the contexts of its
+    /// imports, so importing modules can override values they import.
+    pub fn context(&self, env: &mut Env, name: &str) -> Result<Table> {
+        let mut visited = HashSet::from([name]);
+        self.context_inner(env, &mut visited)
+    }
+
+    fn context_inner<'a>(&'a self, env: &mut Env, visited: &mut HashSet<&'a str>) -> Result<Table> {
+        let mut context = Table::new();
+        for (name, module) in config::modules_matching_globs(&self.imports) {
+            if visited.insert(name) {
+                merge_tables(&mut context, &module.context_inner(env, visited)?);
+            }
+        }
+        merge_tables(&mut context, &self.own_context(env)?);
+        Ok(context)
+    }
+
+    /// Returns the context defined by the module itself. Context files are
+    /// merged first, followed by environment variables and the `context`
+    /// table.
+    fn own_context(&self, env: &mut Env) -> Result<Table> {
+        let mut context = Table::new();
+        for file in self.context_files.iter() {
+            merge_tables(&mut context, &context::load_file(env, file)?);
+        }
+        for (name, var) in self.context_env.iter() {
+            if let Some(value) = var.value(name)? {
+                context.insert(name.clone(), value);
+            }
+        }
+        merge_tables(&mut context, &self.context);
+        Ok(context)
+    }
 }
@@ -1,24 +1,31 @@
 use std::{
-    collections::{BTreeMap, BTreeSet, HashMap},
-    path::PathBuf,
+    collections::{BTreeMap, BTreeSet, btree_map::Entry},
+    path::{Path, PathBuf},
 };
 
 use anyhow::{Context, Result, bail};
 use crossterm::style::Stylize;
 use derive_more::From;
 use indexmap::IndexMap;
-use toml::Value;
+use toml::Table;
 
 use crate::{
+    accounts::{Group, User},
+    config,
     env::Env,
-    fs::{mode::Mode, owner::OwnerIds},
-    packages::PackageManager,
-    state::State,
+    packages::{Package, PackageManager},
+    services::Service,
+    state::{State, path::PathInfo},
+    upon,
+    utils::{merge::merge_tables, pretty::Pretty},
 };
 
 use super::{
-    Module,
-    link::{LinkKind, LinkMethod, ModuleLink},
+    Module, absent, directory,
+    entry::EntryPermissions,
+    hooks::HookKind,
+    link::{LinkKind, LinkMethod, LinkPermissions, ModuleLink},
+    merge,
     source::ModuleSource,
 };
 
@@ -28,57 +35,176 @@ pub struct ModuleSet<'a> {
 }
 
 impl<'a> ModuleSet<'a> {
+    /// Returns the links of every module in the set. Paths and source paths
+    /// are rendered as templates using `context`.
     pub fn links(
         &self,
         env: &mut Env,
-    ) -> Result<impl ExactSizeIterator<Item = ModuleLink> + use<'_>> {
-        let mut links = BTreeSet::new();
+        context: &Table,
+    ) -> Result<impl ExactSizeIterator<Item = ModuleLink<'_>> + use<'_>> {
+        let mut links = BTreeMap::new();
         for (_, module) in self.modules.iter() {
-            let o = module.owner.as_ref().map(|o| o.ids(env)).transpose()?;
-            let m = module.mode;
-            Self::links_inner(o, m, &module.files, &mut links, LinkKind::File)?;
-            Self::links_inner(o, m, &module.hard_links, &mut links, LinkKind::HardLink)?;
-            Self::links_inner(o, m, &module.symlinks, &mut links, LinkKind::Symlink)?;
-            Self::links_inner(o, m, &module.templates, &mut links, LinkKind::Template)?;
+            let c = context;
+            for (kind, entries) in [
+                (LinkKind::File, &module.files),
+                (LinkKind::HardLink, &module.hard_links),
+                (LinkKind::Symlink, &module.symlinks),
+            ] {
+                for (path, entry) in entries.iter() {
+                    let p = module.permissions(env, &entry.permissions)?;
+                    let link = Self::link(p, c, path, &entry.source, kind)?;
+                    Self::insert_link(&mut links, link)?;
+                }
+            }
+            for (path, entry) in module.templates.iter() {
+                let p = module.permissions(env, &entry.permissions)?;
+                let link = Self::link(p, c, path, &entry.source, LinkKind::Template)?
+                    .with_template_globs(&entry.globs);
+                Self::insert_link(&mut links, link)?;
+            }
+            for (path, entry) in module.blocks.iter() {
+                let p = module.permissions(env, &entry.permissions)?;
+                let link = Self::link(p, c, path, &entry.source, LinkKind::Block)?
+                    .with_block_comment(&entry.comment);
+                Self::insert_link(&mut links, link)?;
+            }
         }
-        Ok(links.into_iter())
+        Ok(links.into_values())
     }
 
-    fn links_inner(
-        owner: Option<OwnerIds>,
-        mode: Option<Mode>,
-        input: &'a BTreeMap<PathBuf, ModuleSource>,
-        output: &mut BTreeSet<ModuleLink<'a>>,
+    fn link(
+        permissions: LinkPermissions,
+        context: &Table,
+        path: &'a Path,
+        source: &'a ModuleSource,
         kind: LinkKind,
+    ) -> Result<ModuleLink<'a>> {
+        let path = upon::render_path(path, context)
+            .with_context(|| format!("Couldn't render path {}", path.pretty()))?;
+        let source = source
+            .render(context)
+            .with_context(|| format!("Couldn't render source of {}", path.pretty()))?;
+        Ok(ModuleLink::new(kind, path, source, permissions))
+    }
+
+    fn insert_link(
+        links: &mut BTreeMap<PathBuf, ModuleLink<'a>>,
+        link: ModuleLink<'a>,
     ) -> Result<()> {
-        for (path, source) in input.iter() {
-            let link = ModuleLink::new(kind, path, source, owner, mode);
-            if !output.insert(link) {
-                bail!("Path {} is used multiple times", path.display());
+        match links.entry(link.path().to_path_buf()) {
+            Entry::Occupied(entry) => {
+                bail!("Path {} is used multiple times", entry.key().display())
             }
+            Entry::Vacant(entry) => entry.insert(link),
+        };
+        Ok(())
+    }
+
+    /// Returns the values merged into files by every module in the set. Paths
+    /// are rendered as templates using `context`, and values of the root
+    /// module take precedence.
+    fn merges(&self, env: &mut Env, context: &Table) -> Result<BTreeMap<PathBuf, Table>> {
+        let mut merges: BTreeMap<PathBuf, Table> = BTreeMap::new();
+        for (_, module) in self.modules.iter().rev() {
+            for (path, values) in module.merges.iter() {
+                let path = upon::render_path(path, context)
+                    .with_context(|| format!("Couldn't render path {}", path.pretty()))?;
+                let path = env.untildefy(&path)?.into_owned();
+                merge_tables(merges.entry(path).or_default(), values);
+            }
+        }
+        Ok(merges)
+    }
+
+    fn merge(state: &mut State, module: &str, path: &Path, values: &Table) -> Result<()> {
+        if state.is_path_owned(path) {
+            bail!("Path is used by another module");
         }
+        let original = merge::merge(path, values)?;
+        let info = PathInfo::Merge {
+            values: toml::to_string(values)?,
+            previous: toml::to_string(&original.values)?,
+            created: original.created,
+        };
+        state.add_shared_path(module, path, info);
         Ok(())
     }
 
-    fn context(&self) -> Result<HashMap<&str, &Value>> {
-        let mut context = HashMap::new();
-        for module in self.modules.values() {
-            for (name, value) in module.context.iter() {
-                let name = name.as_str();
-                if context.insert(name, value).is_some() {
-                    let name = name.magenta();
-                    bail!("Variable {name} is defined in multiple contexts",);
+    /// Removes the paths every module in the set declares as absent.
+    fn remove_absent(
+        &self,
+        env: &mut Env,
+        state: &mut State,
+        module: &str,
+        context: &Table,
+    ) -> Result<()> {
+        for (_, m) in self.modules.iter() {
+            for entry in m.absent.iter() {
+                let path = upon::render_path(&entry.path, context)
+                    .with_context(|| format!("Couldn't render path {}", entry.path.pretty()))?;
+                let path = env.untildefy(&path)?.into_owned();
+                // This includes the home directory and the root directory.
+                if env.home_dir().starts_with(&path) {
+                    bail!("Path {} can't be absent", env.tildefy(&path).pretty());
+                } else if state.is_path_or_child_owned(&path) {
+                    bail!("Path {} is used by another module", path.pretty());
+                }
+                if let Some(removal) = absent::remove(env, module, &path, entry.backup)
+                    .with_context(|| format!("Couldn't remove {}", env.tildefy(&path).pretty()))?
+                {
+                    state.add_removed_path(module, &path, removal);
                 }
             }
         }
+        Ok(())
+    }
+
+    /// Creates the directories of every module in the set. Entries without an
+    /// owner or mode use their module's.
+    fn create_directories(
+        &self,
+        env: &mut Env,
+        state: &mut State,
+        module: &str,
+        context: &Table,
+        left_dirs: &mut BTreeSet<PathBuf>,
+    ) -> Result<()> {
+        for (_, m) in self.modules.iter() {
+            for (path, entry) in m.directories.iter() {
+                let mut permissions = m.permissions(env, &EntryPermissions::default())?;
+                if let Some(owner) = &entry.owner {
+                    permissions.owner = Some(owner.ids(env)?);
+                }
+                permissions.mode = entry.mode.or(permissions.dir_mode);
+                let path = upon::render_path(path, context)
+                    .with_context(|| format!("Couldn't render path {}", path.pretty()))?;
+                let path = env.untildefy(&path)?.into_owned();
+                directory::create(env, state, module, &path, &permissions, left_dirs)
+                    .with_context(|| {
+                        format!("Couldn't create directory {}", env.tildefy(&path).pretty())
+                    })?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the context of the set's root module, overridden by the
+    /// context of the current host.
+    pub fn context(&self, env: &mut Env) -> Result<Table> {
+        let (name, module) = self
+            .modules
+            .first()
+            .expect("Module sets should always contain the module they were created from");
+        let mut context = module.context(env, name)?;
+        merge_tables(&mut context, config::host_context());
         Ok(context)
     }
 
-    fn packages(&self) -> BTreeMap<PackageManager, BTreeSet<String>> {
+    fn packages(&self) -> BTreeMap<PackageManager, BTreeSet<Package>> {
         let mut all_packages = BTreeMap::new();
         for (_, module) in &self.modules {
             for (manager, manager_packages) in &module.packages {
-                let packages: &mut BTreeSet<_> = all_packages.entry(*manager).or_default();
+                let packages: &mut BTreeSet<_> = all_packages.entry(manager.clone()).or_default();
                 for package in manager_packages {
                     packages.insert(package.clone());
                 }
@@ -87,18 +213,78 @@ impl<'a> ModuleSet<'a> {
         all_packages
     }
 
+    /// Returns the services of every module in the set. Modules are processed
+    /// in reverse, so the root module's settings take precedence over the
+    /// settings of its imports.
+    pub fn services(&self) -> BTreeMap<String, Service> {
+        let mut services = BTreeMap::new();
+        for (_, module) in self.modules.iter().rev() {
+            for (unit, service) in &module.services {
+                services.insert(unit.clone(), service.clone());
+            }
+        }
+        services
+    }
+
+    /// Returns the users of every module in the set, with the root module's
+    /// users taking precedence.
+    pub fn users(&self) -> BTreeMap<String, User> {
+        let mut users = BTreeMap::new();
+        for (_, module) in self.modules.iter().rev() {
+            users.extend(module.users.clone());
+        }
+        users
+    }
+
+    /// Returns the groups of every module in the set, with the root module's
+    /// groups taking precedence.
+    pub fn groups(&self) -> BTreeMap<String, Group> {
+        let mut groups = BTreeMap::new();
+        for (_, module) in self.modules.iter().rev() {
+            groups.extend(module.groups.clone());
+        }
+        groups
+    }
+
+    /// Runs the hooks of every module in the set. `name` is the name of the
+    /// set's root module.
+    pub fn run_hooks(&self, env: &Env, name: &str, kind: HookKind, paths: &[&Path]) -> Result<()> {
+        for (hook_module, module) in self.modules.iter() {
+            if let Some(hook) = module.hooks.get(kind) {
+                hook.run(env, kind, name, paths)
+                    .with_context(|| format!("Hook of module {} failed", hook_module.magenta()))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Enables the set as module `name`. Directories in `left_dirs` are
+    /// taken over if the set still creates them.
     pub fn enable(
         &self,
         env: &mut Env,
         state: &mut State,
         name: &str,
         method: LinkMethod,
+        left_dirs: &mut BTreeSet<PathBuf>,
     ) -> Result<()> {
-        state.add_module(name, self.packages());
-        for link in self.links(env)? {
-            link.create(env, state, name, &self.context()?, method)
+        state.add_module(name, self.packages(), self.services());
+        // Users and groups are created first, so links can be owned by them.
+        let result = state.create_accounts(name, &self.users(), &self.groups());
+        env.reload_users();
+        result?;
+        let context = self.context(env)?;
+        self.remove_absent(env, state, name, &context)?;
+        self.create_directories(env, state, name, &context, left_dirs)?;
+        for link in self.links(env, &context)? {
+            link.create(env, state, name, &context, method, left_dirs)
                 .with_context(|| format!("Couldn't create link: {link}"))?
         }
+        for (path, values) in self.merges(env, &context)? {
+            Self::merge(state, name, &path, &values).with_context(|| {
+                format!("Couldn't merge values into {}", env.tildefy(&path).pretty())
+            })?;
+        }
         Ok(())
     }
 }
@@ -1,10 +1,14 @@
-use std::path::{Path, PathBuf};
+use std::{
+    borrow::Cow,
+    path::{Path, PathBuf},
+};
 
 use anyhow::{Result, bail};
 use crossterm::style::Stylize;
 use derive_more::Display;
 use serde::Deserialize;
 use sha2::{Digest, Sha256};
+use toml::Table;
 
 use crate::{
     config,
@@ -14,7 +18,7 @@ use crate::{
     utils::sha256::Sha256Hash,
 };
 
-#[derive(Deserialize, Display, Eq, Hash, Ord, PartialEq, PartialOrd)]
+#[derive(Clone, Deserialize, Display, Eq, Hash, Ord, PartialEq, PartialOrd)]
 #[serde(untagged)]
 pub enum ModuleSource {
     Named(SourcePath),
@@ -23,6 +27,17 @@ pub enum ModuleSource {
 }
 
 impl ModuleSource {
+    /// Renders the source path of named sources as a template.
+    pub fn render(&self, context: &Table) -> Result<Cow<'_, Self>> {
+        Ok(match self {
+            ModuleSource::Named(path) => match path.render(context)? {
+                render if render == *path => Cow::Borrowed(self),
+                render => Cow::Owned(ModuleSource::Named(render)),
+            },
+            ModuleSource::Unnamed(_) => Cow::Borrowed(self),
+        })
+    }
+
     pub fn fetch(
         &self,
         env: &mut Env,
@@ -30,7 +45,43 @@ impl ModuleSource {
         module: &str,
         path: &Path,
     ) -> Result<PathBuf> {
-        let (path, info) = match self {
+        let (path, info) = self.locate(env, module, path)?;
+        if let Some((ident, source)) = info
+            && (config::fetch() || !state.is_source_fetched(env, &ident, source))
+        {
+            source.fetch(env, &path)?;
+            state.add_source(&ident, source);
+        }
+        Ok(path)
+    }
+
+    /// Returns the path of the source without fetching it, which fails if the
+    /// source hasn't been fetched yet.
+    pub fn fetched_path(
+        &self,
+        env: &Env,
+        state: &State,
+        module: &str,
+        path: &Path,
+    ) -> Result<PathBuf> {
+        let (path, info) = self.locate(env, module, path)?;
+        if let Some((ident, source)) = info
+            && !state.is_source_fetched(env, &ident, source)
+        {
+            bail!("Source hasn't been fetched, enable the module to fetch it");
+        }
+        Ok(path)
+    }
+
+    /// Returns the path of the source, along with how it's fetched if it isn't
+    /// a static source.
+    fn locate(
+        &self,
+        env: &Env,
+        module: &str,
+        path: &Path,
+    ) -> Result<(PathBuf, Option<(SourceIdent, &HashableSource)>)> {
+        Ok(match self {
             ModuleSource::Named(path) => {
                 if let Some(source) = config::dynamic_source(&path.name) {
                     let ident = SourceIdent::named(path.name.clone());
@@ -50,14 +101,6 @@ impl ModuleSource {
                 let path = env.unnamed_source_dir().join(hash.to_string());
                 (path, Some((ident, source)))
             }
-        };
-
-        if let Some((ident, source)) = info {
-            if config::fetch() || !state.is_source_fetched(env, &ident, source) {
-                source.fetch(env, &path)?;
-                state.add_source(&ident, source);
-            }
-        }
-        Ok(path)
+        })
     }
 }
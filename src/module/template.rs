@@ -0,0 +1,82 @@
+use std::path::Path;
+
+use serde::Deserialize;
+
+use crate::globs::Globs;
+
+use super::{entry::EntryPermissions, source::ModuleSource};
+
+/// A `templates` entry, which is either a source or a table containing a
+/// source, globs deciding which files are rendered and permissions.
+#[derive(Deserialize)]
+#[serde(from = "TemplateEntryDef")]
+pub struct TemplateEntry {
+    pub source: ModuleSource,
+    pub globs: TemplateGlobs,
+    pub permissions: EntryPermissions,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged, deny_unknown_fields)]
+enum TemplateEntryDef {
+    #[serde(rename_all = "kebab-case")]
+    Table {
+        source: ModuleSource,
+        #[serde(default)]
+        template_globs: Option<Globs>,
+        #[serde(default)]
+        copy_globs: Option<Globs>,
+        #[serde(flatten)]
+        permissions: EntryPermissions,
+    },
+    Source(ModuleSource),
+}
+
+impl From<TemplateEntryDef> for TemplateEntry {
+    fn from(value: TemplateEntryDef) -> Self {
+        match value {
+            TemplateEntryDef::Table {
+                source,
+                template_globs,
+                copy_globs,
+                permissions,
+            } => TemplateEntry {
+                source,
+                globs: TemplateGlobs {
+                    template_globs,
+                    copy_globs,
+                },
+                permissions,
+            },
+            TemplateEntryDef::Source(source) => TemplateEntry {
+                source,
+                globs: TemplateGlobs::default(),
+                permissions: EntryPermissions::default(),
+            },
+        }
+    }
+}
+
+/// Globs deciding which files in a templated directory are rendered, and
+/// which are copied verbatim.
+#[derive(Default)]
+pub struct TemplateGlobs {
+    template_globs: Option<Globs>,
+    copy_globs: Option<Globs>,
+}
+
+impl TemplateGlobs {
+    /// Returns whether the file at `rel_path`, which is relative to the root of
+    /// the source, should be rendered. Files matching `copy-globs` are never
+    /// rendered, and if `template-globs` is set, only files matching it are.
+    pub fn is_template(&self, rel_path: &Path) -> bool {
+        !self
+            .copy_globs
+            .as_ref()
+            .is_some_and(|g| g.is_match(rel_path))
+            && self
+                .template_globs
+                .as_ref()
+                .is_none_or(|g| g.is_match(rel_path))
+    }
+}
@@ -1,133 +1,751 @@
 use std::{
-    borrow::Cow,
-    collections::BTreeSet,
+    collections::{BTreeMap, BTreeSet},
+    fmt::{self, Display, Formatter},
     io::{self, BufRead, Write},
     process::{Command, Stdio},
 };
 
-use anyhow::{Context, Result};
+use anyhow::{Context, Result, anyhow, bail};
 use bincode::{Decode, Encode};
-use derive_more::{Display, From};
+use crossterm::style::Stylize;
+use derive_more::Display;
 use itertools::Itertools;
 use serde::Deserialize;
 
-use crate::config;
+use crate::{
+    config,
+    utils::{pretty::Pretty, version::VersionReq},
+};
 
-#[derive(
-    Clone, Copy, Debug, Decode, Deserialize, Display, Encode, Eq, From, Ord, PartialEq, PartialOrd,
-)]
-#[serde(rename_all = "kebab-case", expecting = "a supported package manager")]
+#[derive(Clone, Debug, Decode, Deserialize, Display, Encode, Eq, Ord, PartialEq, PartialOrd)]
+#[serde(from = "String")]
 pub enum PackageManager {
     #[display("pacman")]
     Pacman,
     #[display("paru")]
     Paru,
+    #[display("apt")]
+    Apt,
+    #[display("dnf")]
+    Dnf,
+    #[display("cargo")]
+    Cargo,
+    #[display("pipx")]
+    Pipx,
+    #[display("npm")]
+    Npm,
+    #[display("go")]
+    Go,
+    #[display("flatpak")]
+    Flatpak,
+    #[display("flatpak-user")]
+    FlatpakUser,
+    /// A package manager defined in the config file.
+    #[display("{_0}")]
+    Custom(String),
+}
+
+impl From<String> for PackageManager {
+    fn from(name: String) -> Self {
+        match name.as_str() {
+            "pacman" => PackageManager::Pacman,
+            "paru" => PackageManager::Paru,
+            "apt" => PackageManager::Apt,
+            "dnf" => PackageManager::Dnf,
+            "cargo" => PackageManager::Cargo,
+            "pipx" => PackageManager::Pipx,
+            "npm" => PackageManager::Npm,
+            "go" => PackageManager::Go,
+            "flatpak" => PackageManager::Flatpak,
+            "flatpak-user" => PackageManager::FlatpakUser,
+            _ => PackageManager::Custom(name),
+        }
+    }
 }
 
 impl PackageManager {
-    pub fn sync(
-        &self,
-        install: bool,
-        uninstall: bool,
-        packages: &BTreeSet<Cow<str>>,
-    ) -> Result<()> {
-        let (i, u, p) = (install, uninstall, packages);
-        match self {
-            PackageManager::Pacman => Self::sync_inner::<Pacman>(i, u, p),
-            PackageManager::Paru => Self::sync_inner::<Paru>(i, u, p),
-        }
-    }
-
-    pub fn sync_inner<M: Manager>(
-        install: bool,
-        uninstall: bool,
-        packages: &BTreeSet<Cow<str>>,
-    ) -> Result<()> {
-        let installed = Self::list::<M>()?;
-        let (old, new) = Self::diff(&installed, packages);
+    pub fn is_custom(&self) -> bool {
+        matches!(self, PackageManager::Custom(_))
+    }
+
+    /// Lists installed packages and returns the changes needed to match
+    /// `packages`, without making them.
+    pub fn plan<'a>(
+        &'a self,
+        options: SyncOptions,
+        packages: &BTreeSet<&'a Package>,
+    ) -> Result<SyncPlan<'a>> {
+        let commands = self.commands()?;
+        let installed = commands.list()?;
+        let (old, new) = commands.diff(&installed, packages);
+        Ok(SyncPlan {
+            manager: self,
+            commands,
+            options,
+            old: old.into_iter().map(str::to_string).collect(),
+            new,
+        })
+    }
+
+    fn commands(&self) -> Result<Commands<'static>> {
+        Ok(match self {
+            PackageManager::Pacman => Commands::of::<Pacman>(),
+            PackageManager::Paru => Commands::of::<Paru>(),
+            PackageManager::Apt => Commands::of::<Apt>(),
+            PackageManager::Dnf => Commands::of::<Dnf>(),
+            PackageManager::Cargo => Commands::of::<Cargo>(),
+            PackageManager::Pipx => Commands::of::<Pipx>(),
+            PackageManager::Npm => Commands::of::<Npm>(),
+            PackageManager::Go => Commands::of::<Go>(),
+            PackageManager::Flatpak => Commands::of::<Flatpak>(),
+            PackageManager::FlatpakUser => Commands::of::<FlatpakUser>(),
+            PackageManager::Custom(name) => config::package_manager(name)
+                .ok_or_else(|| anyhow!("Package manager `{name}` isn't defined"))?
+                .commands(),
+        })
+    }
+}
+
+#[derive(Clone, Copy)]
+pub struct SyncOptions {
+    pub install: bool,
+    pub uninstall: bool,
+    /// Whether packages should be uninstalled without confirmation, even if
+    /// there are more of them than the configured limit.
+    pub force: bool,
+    /// Whether packages should only be shown instead of being installed and
+    /// uninstalled.
+    pub dry_run: bool,
+}
+
+/// The packages a package manager has to install and uninstall during a sync.
+pub struct SyncPlan<'a> {
+    manager: &'a PackageManager,
+    commands: Commands<'static>,
+    options: SyncOptions,
+    old: Vec<String>,
+    new: Vec<&'a Package>,
+}
+
+impl SyncPlan<'_> {
+    pub fn print(&self) {
+        let manager = self.manager;
+        if self.options.install && !self.new.is_empty() {
+            let names: Vec<_> = self.new.iter().map(|p| p.name.as_str()).collect();
+            println!("Install with {manager}: {}", names.as_slice().pretty());
+        }
+        if self.options.uninstall && !self.old.is_empty() {
+            println!("Uninstall with {manager}: {}", self.old.as_slice().pretty());
+        }
+    }
+
+    pub fn run(&self) -> Result<()> {
+        let (options, commands) = (self.options, &self.commands);
         // Installing comes first so dependencies aren't reinstalled after
         // being uninstalled.
-        if install && !new.is_empty() {
-            Self::install::<M>(&new)?;
+        if options.install && !self.new.is_empty() {
+            commands.install(&self.new)?;
+        }
+        let old: Vec<_> = self.old.iter().map(String::as_str).collect();
+        if options.uninstall
+            && !old.is_empty()
+            && (options.force || Commands::confirm_uninstall(self.manager, &old)?)
+        {
+            commands.uninstall(&old)?;
+        }
+        Ok(())
+    }
+}
+
+/// A package wanted by a module, which is either just the name of a package,
+/// or a table with additional requirements.
+#[derive(Clone, Debug, Decode, Deserialize, Encode, Eq, Ord, PartialEq, PartialOrd)]
+#[serde(from = "PackageDef")]
+pub struct Package {
+    pub name: String,
+    /// Version the package must have. The package is reinstalled if the
+    /// installed version doesn't match.
+    version: Option<VersionReq>,
+    /// Repository the package is installed from.
+    repo: Option<String>,
+    /// Additional arguments passed to the package manager when installing the
+    /// package.
+    options: Vec<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged, deny_unknown_fields)]
+enum PackageDef {
+    Name(String),
+    #[serde(rename_all = "kebab-case")]
+    Table {
+        name: String,
+        #[serde(default)]
+        version: Option<VersionReq>,
+        #[serde(default)]
+        repo: Option<String>,
+        #[serde(default)]
+        options: Vec<String>,
+    },
+}
+
+impl From<PackageDef> for Package {
+    fn from(value: PackageDef) -> Self {
+        match value {
+            PackageDef::Name(name) => Package {
+                name,
+                version: None,
+                repo: None,
+                options: Vec::new(),
+            },
+            PackageDef::Table {
+                name,
+                version,
+                repo,
+                options,
+            } => Package {
+                name,
+                version,
+                repo,
+                options,
+            },
         }
-        if uninstall && !old.is_empty() {
-            Self::uninstall::<M>(&old)?;
+    }
+}
+
+impl From<String> for Package {
+    fn from(name: String) -> Self {
+        PackageDef::Name(name).into()
+    }
+}
+
+impl Display for Package {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        self.name.as_str().magenta().fmt(f)?;
+        if let Some(version) = &self.version {
+            write!(f, " {}", version.to_string().yellow())?;
+        }
+        if let Some(repo) = &self.repo {
+            write!(f, " from {repo}")?;
         }
         Ok(())
     }
+}
+
+/// Gets the name and version of a package from a line in the output of the
+/// command listing installed packages.
+type ParsePackage = fn(&str) -> Option<(&str, Option<&str>)>;
+
+/// The commands and behavior of a package manager, which is either taken from
+/// a `Manager` implementation or from a package manager defined in the config
+/// file.
+struct Commands<'a> {
+    needs_root: bool,
+    stdin: bool,
+    list: Vec<&'a str>,
+    install: Vec<&'a str>,
+    uninstall: Vec<&'a str>,
+    bundled: &'a [&'a str],
+    package: ParsePackage,
+    name: fn(&str) -> &str,
+    with_repo: fn(&str, &str) -> Option<String>,
+}
+
+impl Commands<'_> {
+    fn of<M: Manager>() -> Self {
+        Commands {
+            needs_root: M::NEEDS_ROOT,
+            stdin: M::STDIN,
+            list: M::LIST.to_vec(),
+            install: M::INSTALL.to_vec(),
+            uninstall: M::UNINSTALL.to_vec(),
+            bundled: M::BUNDLED,
+            package: M::package,
+            name: M::name,
+            with_repo: M::with_repo,
+        }
+    }
 
-    fn list<'a, M: Manager>() -> Result<BTreeSet<Cow<'a, str>>> {
-        let command = M::LIST;
-        Command::new(command[0])
+    fn confirm_uninstall(manager: &PackageManager, packages: &[&str]) -> Result<bool> {
+        let max = config::max_uninstalls();
+        if packages.len() > max {
+            bail!(
+                "Refusing to uninstall {} packages with {manager} (the limit is {max}, use `--force` to ignore it): {}",
+                packages.len(),
+                packages.pretty()
+            );
+        }
+        print!(
+            "Uninstall {} packages with {manager}? [y/N] ",
+            packages.len()
+        );
+        io::stdout().flush()?;
+        let mut input = "".to_string();
+        io::stdin().read_line(&mut input)?;
+        Ok(input.trim().eq_ignore_ascii_case("y"))
+    }
+
+    /// Returns the names and, if they're known, the versions of installed
+    /// packages.
+    fn list(&self) -> Result<BTreeMap<String, Option<String>>> {
+        let command = &self.list;
+        let output = Command::new(command[0])
             .args(&command[1..])
             .output()
-            .context("Couldn't run command to list installed packages")?
+            .context("Couldn't run command to list installed packages")?;
+        if !output.status.success() {
+            bail!(
+                "Command to list installed packages failed ({}): {}",
+                output.status,
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+        }
+        output
             .stdout
             .lines()
-            .map_ok(Cow::Owned)
+            .filter_map_ok(|line| {
+                (self.package)(&line)
+                    .map(|(name, version)| (name.to_string(), version.map(str::to_string)))
+            })
             .collect::<Result<_, _>>()
             .context("Couldn't parse list of installed packages")
     }
 
-    fn diff<'a>(
-        installed: &'a BTreeSet<Cow<str>>,
-        wanted: &'a BTreeSet<Cow<str>>,
-    ) -> (Vec<&'a str>, Vec<&'a str>) {
-        let old = installed.difference(wanted).map(|s| s.as_ref()).collect();
-        let new = wanted.difference(installed).map(|s| s.as_ref()).collect();
+    /// Returns the installed packages that aren't wanted, and the wanted
+    /// packages that either aren't installed or are installed with a version
+    /// that doesn't match their requirement.
+    fn diff<'i, 'p>(
+        &self,
+        installed: &'i BTreeMap<String, Option<String>>,
+        wanted: &BTreeSet<&'p Package>,
+    ) -> (Vec<&'i str>, Vec<&'p Package>) {
+        let wanted_names: BTreeSet<_> = wanted
+            .iter()
+            .map(|package| (self.name)(&package.name))
+            .collect();
+        let ignored = config::ignored_packages();
+        let old = installed
+            .keys()
+            .map(|s| s.as_str())
+            .filter(|s| {
+                !wanted_names.contains(s) && !ignored.is_match(s) && !self.bundled.contains(s)
+            })
+            .collect();
+        let new = wanted
+            .iter()
+            .filter(|package| match installed.get((self.name)(&package.name)) {
+                None => true,
+                Some(version) => package
+                    .version
+                    .as_ref()
+                    .zip(version.as_ref())
+                    .is_some_and(|(req, version)| !req.matches(version)),
+            })
+            .copied()
+            .collect();
         (old, new)
     }
 
-    fn update_packages<M: Manager>(args: &[&str], packages: &[&str]) -> io::Result<()> {
-        let mut command = M::NEEDS_ROOT
-            .then(config::root_command)
-            .flatten()
-            .unwrap_or_else(|| Command::new(args[0]));
-        args.get(!M::NEEDS_ROOT as usize..)
-            .map(|args| command.args(args));
-        let mut child = command.stdin(Stdio::piped()).spawn()?;
-        let stdin = child.stdin.as_mut().unwrap();
+    fn update_packages(&self, args: &[&str], options: &[String], packages: &[&str]) -> Result<()> {
+        let mut command = match self.needs_root.then(config::root_command).flatten() {
+            Some(mut command) => {
+                command.args(args);
+                command
+            }
+            None => {
+                let mut command = Command::new(args[0]);
+                command.args(&args[1..]);
+                command
+            }
+        };
+        command.args(options);
+        let status = if self.stdin {
+            let mut child = command.stdin(Stdio::piped()).spawn()?;
+            let stdin = child.stdin.as_mut().unwrap();
+            for package in packages {
+                stdin.write_all(package.as_bytes())?;
+                stdin.write_all(b"\n")?;
+            }
+            child.wait()?
+        } else {
+            command.args(packages).status()?
+        };
+        if !status.success() {
+            bail!("Package manager failed ({status})");
+        }
+        Ok(())
+    }
+
+    /// Installs packages. Packages with different options are installed
+    /// separately.
+    fn install(&self, packages: &[&Package]) -> Result<()> {
+        let mut groups: BTreeMap<&[String], Vec<String>> = BTreeMap::new();
         for package in packages {
-            stdin.write_all(package.as_bytes())?;
-            stdin.write_all(b"\n")?;
+            let name = match &package.repo {
+                Some(repo) => (self.with_repo)(&package.name, repo).ok_or_else(|| {
+                    anyhow!("Package manager doesn't support installing from repositories")
+                })?,
+                None => package.name.clone(),
+            };
+            groups.entry(&package.options).or_default().push(name);
+        }
+        for (options, names) in groups {
+            let names: Vec<_> = names.iter().map(|s| s.as_str()).collect();
+            self.update_packages(&self.install, options, &names)
+                .context("Couldn't install packages")?;
         }
-        child.wait()?;
         Ok(())
     }
 
-    fn install<M: Manager>(packages: &[&str]) -> Result<()> {
-        Self::update_packages::<M>(M::INSTALL, packages).context("Couldn't install packages")
+    fn uninstall(&self, packages: &[&str]) -> Result<()> {
+        self.update_packages(&self.uninstall, &[], packages)
+            .context("Couldn't uninstall packages")
     }
+}
+
+/// A package manager defined in the config file.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+pub struct CustomManager {
+    #[serde(default = "CustomManager::default_needs_root")]
+    needs_root: bool,
+    #[serde(default)]
+    stdin: bool,
+    list: Vec<String>,
+    install: Vec<String>,
+    uninstall: Vec<String>,
+}
 
-    fn uninstall<M: Manager>(packages: &[&str]) -> Result<()> {
-        Self::update_packages::<M>(M::UNINSTALL, packages).context("Couldn't uninstall packages")
+impl CustomManager {
+    fn default_needs_root() -> bool {
+        true
+    }
+
+    pub fn check(&self) -> Result<()> {
+        for (field, command) in [
+            ("list", &self.list),
+            ("install", &self.install),
+            ("uninstall", &self.uninstall),
+        ] {
+            if command.is_empty() {
+                bail!("Command `{field}` is empty");
+            }
+        }
+        Ok(())
+    }
+
+    fn commands(&self) -> Commands<'_> {
+        Commands {
+            needs_root: self.needs_root,
+            stdin: self.stdin,
+            list: self.list.iter().map(|s| s.as_str()).collect(),
+            install: self.install.iter().map(|s| s.as_str()).collect(),
+            uninstall: self.uninstall.iter().map(|s| s.as_str()).collect(),
+            bundled: &[],
+            package: default_package,
+            name: |package| package,
+            with_repo: |_, _| None,
+        }
     }
 }
 
 pub trait Manager {
     /// Whether the package manager needs to be run as root.
     const NEEDS_ROOT: bool = true;
+    /// Whether packages are written to stdin instead of being passed as
+    /// arguments.
+    const STDIN: bool = true;
     /// Command used to get a list of currently installed packages.
     const LIST: &[&str];
     /// Command used to install packages.
     const INSTALL: &[&str];
     /// Command used to uninstall packages.
     const UNINSTALL: &[&str];
+    /// Packages that are installed along with the package manager itself,
+    /// which are never uninstalled.
+    const BUNDLED: &[&str] = &[];
+
+    /// Gets the name and version of a package from a line in the output of
+    /// `LIST`.
+    fn package(line: &str) -> Option<(&str, Option<&str>)> {
+        default_package(line)
+    }
+
+    /// Gets the name a package is listed as by `LIST`.
+    fn name(package: &str) -> &str {
+        package
+    }
+
+    /// Gets the name used to install a package from a specific repository, or
+    /// `None` if the package manager doesn't support repositories.
+    fn with_repo(_package: &str, _repo: &str) -> Option<String> {
+        None
+    }
+}
+
+/// Parses lines containing the name of a package, optionally followed by its
+/// version.
+fn default_package(line: &str) -> Option<(&str, Option<&str>)> {
+    let mut words = line.split_whitespace();
+    words.next().map(|name| (name, words.next()))
 }
 
 struct Pacman;
 
 impl Manager for Pacman {
-    const LIST: &[&str] = &["pacman", "-Qqen"];
+    const LIST: &[&str] = &["pacman", "-Qen"];
     const INSTALL: &[&str] = &["pacman", "-S", "-"];
     const UNINSTALL: &[&str] = &["pacman", "-Rnsu", "-"];
+
+    fn with_repo(package: &str, repo: &str) -> Option<String> {
+        Some(format!("{repo}/{package}"))
+    }
 }
 
 struct Paru;
 
 impl Manager for Paru {
     const NEEDS_ROOT: bool = false;
-    const LIST: &[&str] = &["paru", "-Qqem"];
+    const LIST: &[&str] = &["paru", "-Qem"];
     const INSTALL: &[&str] = &["paru", "-S", "-"];
     const UNINSTALL: &[&str] = &["paru", "-Rnsu", "-"];
+
+    fn with_repo(package: &str, repo: &str) -> Option<String> {
+        Pacman::with_repo(package, repo)
+    }
+}
+
+struct Apt;
+
+impl Manager for Apt {
+    const STDIN: bool = false;
+    const LIST: &[&str] = &[
+        "sh",
+        "-c",
+        r#"apt-mark showmanual | xargs -r dpkg-query -W -f='${Package} ${Version}\n'"#,
+    ];
+    const INSTALL: &[&str] = &[
+        "env",
+        "DEBIAN_FRONTEND=noninteractive",
+        "apt-get",
+        "install",
+        "-y",
+        "-q",
+    ];
+    const UNINSTALL: &[&str] = &[
+        "env",
+        "DEBIAN_FRONTEND=noninteractive",
+        "apt-get",
+        "purge",
+        "--auto-remove",
+        "-y",
+        "-q",
+    ];
+
+    /// Apt installs packages from a specific release with `<name>/<release>`.
+    fn with_repo(package: &str, repo: &str) -> Option<String> {
+        Some(format!("{package}/{repo}"))
+    }
+}
+
+struct Dnf;
+
+impl Manager for Dnf {
+    const STDIN: bool = false;
+    const LIST: &[&str] = &[
+        "dnf",
+        "repoquery",
+        "--userinstalled",
+        "--queryformat",
+        "%{name} %{version}\n",
+    ];
+    const INSTALL: &[&str] = &["dnf", "install", "-y"];
+    const UNINSTALL: &[&str] = &["dnf", "remove", "-y"];
+}
+
+struct Cargo;
+
+impl Manager for Cargo {
+    const NEEDS_ROOT: bool = false;
+    const STDIN: bool = false;
+    const LIST: &[&str] = &["cargo", "install", "--list"];
+    const INSTALL: &[&str] = &["cargo", "install"];
+    const UNINSTALL: &[&str] = &["cargo", "uninstall"];
+
+    /// Packages are listed as `<name> v<version>:`, followed by indented lines
+    /// containing their binaries.
+    fn package(line: &str) -> Option<(&str, Option<&str>)> {
+        (!line.starts_with(char::is_whitespace))
+            .then(|| line.split_once(' '))
+            .flatten()
+            .map(|(name, rest)| {
+                let version = rest.split([' ', ':']).next().filter(|s| !s.is_empty());
+                (name, version)
+            })
+    }
 }
+
+struct Pipx;
+
+impl Manager for Pipx {
+    const NEEDS_ROOT: bool = false;
+    const STDIN: bool = false;
+    const LIST: &[&str] = &["pipx", "list", "--short"];
+    const INSTALL: &[&str] = &["pipx", "install"];
+    // `pipx uninstall` only accepts one package.
+    const UNINSTALL: &[&str] = &[
+        "sh",
+        "-c",
+        r#"for package; do pipx uninstall "$package" || exit; done"#,
+        "sh",
+    ];
+}
+
+struct Npm;
+
+impl Manager for Npm {
+    const NEEDS_ROOT: bool = false;
+    const STDIN: bool = false;
+    const LIST: &[&str] = &[
+        "npm",
+        "list",
+        "--global",
+        "--depth=0",
+        "--parseable",
+        "--long",
+    ];
+    const INSTALL: &[&str] = &["npm", "install", "--global"];
+    const UNINSTALL: &[&str] = &["npm", "uninstall", "--global"];
+    const BUNDLED: &[&str] = &["corepack", "npm"];
+
+    /// Packages are listed as `<path>:<name>@<version>:<...>`, where the path
+    /// is inside of the global `node_modules` directory, which is listed first.
+    fn package(line: &str) -> Option<(&str, Option<&str>)> {
+        let (path, rest) = line.split_once(':')?;
+        if !path.contains("node_modules/") {
+            return None;
+        }
+        let package = rest.split(':').next()?;
+        match package.rsplit_once('@') {
+            Some((name, version)) if !name.is_empty() => Some((name, Some(version))),
+            _ => Some((package, None)),
+        }
+    }
+}
+
+struct Go;
+
+impl Manager for Go {
+    const NEEDS_ROOT: bool = false;
+    const STDIN: bool = false;
+    // Go doesn't keep track of installed packages, so the binaries in `GOBIN`
+    // are listed by the package path they were built from and the version of
+    // their module.
+    const LIST: &[&str] = &[
+        "sh",
+        "-c",
+        r#"bin=$(go env GOBIN)
+        for file in "${bin:-$(go env GOPATH)/bin}"/*; do
+            go version -m "$file" 2>/dev/null |
+                awk '$1 == "path" { path = $2 } $1 == "mod" { print path, $3 }'
+        done"#,
+    ];
+    const INSTALL: &[&str] = &[
+        "sh",
+        "-c",
+        r#"options=
+        for package; do
+            case $package in
+                -*) options="$options $package" ;;
+                *) go install $options "$package@latest" || exit ;;
+            esac
+        done"#,
+        "sh",
+    ];
+    const UNINSTALL: &[&str] = &[
+        "sh",
+        "-c",
+        r#"bin=$(go env GOBIN)
+        for file in "${bin:-$(go env GOPATH)/bin}"/*; do
+            path=$(go version -m "$file" 2>/dev/null | awk '$1 == "path" { print $2 }')
+            for package; do
+                if [ "$path" = "$package" ]; then
+                    rm -f "$file" || exit
+                fi
+            done
+        done"#,
+        "sh",
+    ];
+}
+
+/// Packages can be installed from a specific remote by prefixing them with the
+/// name of the remote, like `flathub:org.mozilla.firefox`.
+struct Flatpak;
+
+impl Flatpak {
+    fn name(package: &str) -> &str {
+        package.split_once(':').map_or(package, |(_, name)| name)
+    }
+
+    fn with_repo(package: &str, repo: &str) -> Option<String> {
+        Some(format!("{repo}:{package}"))
+    }
+}
+
+/// Implements `Manager` for a flatpak backend that manages applications of the
+/// given installation, which is either `--system` or `--user`.
+macro_rules! flatpak_manager {
+    ($manager:ident, $installation:literal, $needs_root:literal) => {
+        impl Manager for $manager {
+            const NEEDS_ROOT: bool = $needs_root;
+            const STDIN: bool = false;
+            const LIST: &[&str] = &[
+                "flatpak",
+                "list",
+                "--app",
+                $installation,
+                "--columns=application,version",
+            ];
+            const INSTALL: &[&str] = &[
+                "sh",
+                "-c",
+                concat!(
+                    r#"options=
+                    for package; do
+                        case $package in
+                            -*) options="$options $package" ;;
+                            *:*) flatpak install "#,
+                    $installation,
+                    r#" -y --noninteractive $options "${package%%:*}" "${package#*:}" ;;
+                            *) flatpak install "#,
+                    $installation,
+                    r#" -y --noninteractive $options "$package" ;;
+                        esac || exit
+                    done"#
+                ),
+                "sh",
+            ];
+            const UNINSTALL: &[&str] = &[
+                "flatpak",
+                "uninstall",
+                $installation,
+                "-y",
+                "--noninteractive",
+            ];
+
+            fn name(package: &str) -> &str {
+                Flatpak::name(package)
+            }
+
+            fn with_repo(package: &str, repo: &str) -> Option<String> {
+                Flatpak::with_repo(package, repo)
+            }
+        }
+    };
+}
+
+flatpak_manager!(Flatpak, "--system", true);
+
+struct FlatpakUser;
+
+flatpak_manager!(FlatpakUser, "--user", false);
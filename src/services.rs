@@ -0,0 +1,253 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt::{self, Formatter},
+    path::Path,
+    process::Command,
+};
+
+use anyhow::{Context, Result, bail};
+use bincode::{Decode, Encode};
+use crossterm::style::Stylize;
+use derive_more::Display;
+use serde::Deserialize;
+
+use crate::config;
+
+#[derive(
+    Clone,
+    Copy,
+    Debug,
+    Decode,
+    Default,
+    Deserialize,
+    Display,
+    Encode,
+    Eq,
+    Ord,
+    PartialEq,
+    PartialOrd,
+)]
+#[serde(rename_all = "kebab-case")]
+pub enum ServiceScope {
+    #[default]
+    #[display("system")]
+    System,
+    #[display("user")]
+    User,
+}
+
+/// A systemd unit managed by a module. Masked units can't be enabled or
+/// started.
+#[derive(Clone, Decode, Deserialize, Encode, Eq, PartialEq)]
+#[serde(try_from = "ServiceDef")]
+pub struct Service {
+    scope: ServiceScope,
+    enable: bool,
+    start: bool,
+    mask: bool,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+struct ServiceDef {
+    #[serde(default)]
+    scope: ServiceScope,
+    #[serde(default)]
+    enable: bool,
+    #[serde(default)]
+    start: bool,
+    #[serde(default)]
+    mask: bool,
+}
+
+impl TryFrom<ServiceDef> for Service {
+    type Error = &'static str;
+
+    fn try_from(value: ServiceDef) -> Result<Self, Self::Error> {
+        if value.mask && (value.enable || value.start) {
+            return Err("Masked units can't be enabled or started");
+        }
+        Ok(Service {
+            scope: value.scope,
+            enable: value.enable,
+            start: value.start,
+            mask: value.mask,
+        })
+    }
+}
+
+impl fmt::Display for Service {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let states: Vec<_> = [
+            (self.enable, "enabled"),
+            (self.start, "started"),
+            (self.mask, "masked"),
+        ]
+        .into_iter()
+        .filter_map(|(is_set, state)| is_set.then_some(state))
+        .collect();
+        write!(f, "{}", self.scope.to_string().blue())?;
+        if !states.is_empty() {
+            write!(f, ", {}", states.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+/// Reverts units in `old` that aren't in `new` with the same settings, by
+/// stopping, disabling and unmasking them.
+pub fn stop(old: &BTreeMap<String, Service>, new: &BTreeMap<String, Service>) -> Result<()> {
+    for (unit, service) in old {
+        if new.get(unit) == Some(service) {
+            continue;
+        }
+        if service.start {
+            systemctl(service.scope, &["stop", unit])?;
+        }
+        if service.enable {
+            systemctl(service.scope, &["disable", unit])?;
+        }
+        if service.mask {
+            systemctl(service.scope, &["unmask", unit])?;
+        }
+    }
+    Ok(())
+}
+
+/// Reloads units whose files changed, applies units in `new` that aren't in
+/// `old` with the same settings, and restarts running units whose files
+/// changed and weren't just started.
+pub fn start(
+    old: &BTreeMap<String, Service>,
+    new: &BTreeMap<String, Service>,
+    changed: &[&Path],
+) -> Result<()> {
+    let mut changed = reload(changed)?;
+    for (unit, service) in new {
+        if old.get(unit) == Some(service) {
+            continue;
+        }
+        if service.mask {
+            systemctl(service.scope, &["mask", unit])?;
+        }
+        if service.enable {
+            systemctl(service.scope, &["enable", unit])?;
+        }
+        if service.start {
+            systemctl(service.scope, &["start", unit])?;
+            changed.remove(&(service.scope, unit.clone()));
+        }
+    }
+    for (scope, unit) in changed {
+        systemctl(scope, &["try-restart", &unit])?;
+    }
+    Ok(())
+}
+
+/// Runs `daemon-reload` for every scope with changed unit files, and returns
+/// the changed units.
+pub fn reload(changed: &[&Path]) -> Result<BTreeSet<(ServiceScope, String)>> {
+    let units: BTreeSet<_> = changed.iter().filter_map(|path| unit(path)).collect();
+    let scopes: BTreeSet<_> = units.iter().map(|(scope, _)| *scope).collect();
+    for scope in scopes {
+        systemctl(scope, &["daemon-reload"])?;
+    }
+    Ok(units)
+}
+
+const UNIT_KINDS: &[&str] = &[
+    "automount",
+    "mount",
+    "path",
+    "service",
+    "slice",
+    "socket",
+    "swap",
+    "target",
+    "timer",
+];
+
+/// Returns the scope and name of the unit a path belongs to, if it's a unit
+/// file or a drop-in file inside of a `systemd/system` or `systemd/user`
+/// directory.
+fn unit(path: &Path) -> Option<(ServiceScope, String)> {
+    let scope = |dir: &Path| {
+        if dir.ends_with("systemd/system") {
+            Some(ServiceScope::System)
+        } else if dir.ends_with("systemd/user") {
+            Some(ServiceScope::User)
+        } else {
+            None
+        }
+    };
+    let parent = path.parent()?;
+    let name = path.file_name()?.to_str()?;
+    if let Some(scope) = scope(parent) {
+        let (_, kind) = name.rsplit_once('.')?;
+        return UNIT_KINDS
+            .contains(&kind)
+            .then(|| (scope, name.to_string()));
+    }
+    let unit = parent.file_name()?.to_str()?.strip_suffix(".d")?;
+    scope(parent.parent()?).map(|scope| (scope, unit.to_string()))
+}
+
+fn systemctl(scope: ServiceScope, args: &[&str]) -> Result<()> {
+    let systemctl = config::systemctl_command();
+    let mut command = match scope {
+        ServiceScope::System => match config::root_command() {
+            Some(mut command) => {
+                command.args(systemctl);
+                command
+            }
+            None => {
+                let mut command = Command::new(&systemctl[0]);
+                command.args(&systemctl[1..]);
+                command
+            }
+        },
+        ServiceScope::User => {
+            let mut command = Command::new(&systemctl[0]);
+            command.args(&systemctl[1..]).arg("--user");
+            command
+        }
+    };
+    let status = command
+        .args(args)
+        .status()
+        .with_context(|| format!("Couldn't run `systemctl {}`", args.join(" ")))?;
+    if !status.success() {
+        bail!("`systemctl {}` failed ({status})", args.join(" "));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn find_units() {
+        let paths = [
+            (
+                "/etc/systemd/system/foo.service",
+                Some((ServiceScope::System, "foo.service")),
+            ),
+            (
+                "/etc/systemd/system/foo.service.d/override.conf",
+                Some((ServiceScope::System, "foo.service")),
+            ),
+            (
+                "/home/user/.config/systemd/user/bar.timer",
+                Some((ServiceScope::User, "bar.timer")),
+            ),
+            ("/etc/systemd/system/multi-user.target.wants", None),
+            ("/etc/systemd/system.conf", None),
+            ("/etc/foo.service", None),
+        ];
+        for (path, unit_) in paths {
+            let unit_ = unit_.map(|(scope, name)| (scope, name.to_string()));
+            assert_eq!(unit(Path::new(path)), unit_, "{path}");
+        }
+    }
+}
@@ -24,10 +24,10 @@ impl HashableSource {
     }
 
     pub fn check(&self, path: &Path) -> Result<()> {
-        if let Some(hash) = &self.hash {
-            if Sha256Hash::from_path(path).context("Couldn't calculate hash")? != *hash {
-                bail!("Contents don't match hash");
-            }
+        if let Some(hash) = &self.hash
+            && Sha256Hash::from_path(path).context("Couldn't calculate hash")? != *hash
+        {
+            bail!("Contents don't match hash");
         }
         Ok(())
     }
@@ -11,12 +11,13 @@ use serde::{
     de::{self, Visitor},
 };
 use thiserror::Error;
+use toml::Table;
 
-use crate::{env::Env, utils::pretty::Pretty};
+use crate::{env::Env, upon, utils::pretty::Pretty};
 
 use super::name::{ParseSourceNameError, SourceName};
 
-#[derive(Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+#[derive(Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
 pub struct SourcePath {
     pub name: SourceName,
     pub path: Option<PathBuf>,
@@ -38,6 +39,16 @@ impl SourcePath {
         }
         full_path
     }
+
+    /// Renders the source name and path as templates.
+    pub fn render(&self, context: &Table) -> Result<Self> {
+        let name = upon::render(&self.name, context)?.parse()?;
+        let path = match &self.path {
+            Some(path) => Some(upon::render_path(path, context)?.into_owned()),
+            None => None,
+        };
+        Ok(SourcePath { name, path })
+    }
 }
 
 impl Display for SourcePath {
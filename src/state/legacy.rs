@@ -0,0 +1,90 @@
+//! The state format used before state files were versioned. It's migrated to
+//! the current format when it's loaded, so modules enabled by older versions
+//! can still be disabled.
+
+use std::{
+    collections::{BTreeMap, BTreeSet, HashSet},
+    path::PathBuf,
+};
+
+use bincode::Decode;
+
+use crate::{
+    packages::PackageManager,
+    source::{hashable::HashableSource, ident::SourceIdent},
+    utils::sha256::Sha256Hash,
+};
+
+use super::{State, module::ModuleState, path::PathInfo};
+
+#[derive(Decode)]
+pub struct LegacyState {
+    sources: BTreeMap<SourceIdent, HashableSource>,
+    modules: BTreeMap<String, LegacyModuleState>,
+    paths: HashSet<PathBuf>,
+}
+
+#[derive(Decode)]
+struct LegacyModuleState {
+    paths: Vec<(PathBuf, LegacyPathInfo)>,
+    packages: BTreeMap<PackageManager, BTreeSet<String>>,
+}
+
+#[derive(Decode)]
+enum LegacyPathInfo {
+    Directory,
+    File { size: u64, hash: Sha256Hash },
+    HardLink { size: u64, hash: Sha256Hash },
+    Symlink { original: PathBuf },
+}
+
+impl From<LegacyState> for State {
+    fn from(legacy: LegacyState) -> Self {
+        let modules = legacy
+            .modules
+            .into_iter()
+            .map(|(name, module)| (name, module.into()))
+            .collect();
+        State {
+            sources: legacy.sources,
+            modules,
+            paths: legacy.paths,
+            accounts: Default::default(),
+        }
+    }
+}
+
+impl From<LegacyModuleState> for ModuleState {
+    fn from(legacy: LegacyModuleState) -> Self {
+        let packages = legacy
+            .packages
+            .into_iter()
+            .map(|(manager, names)| (manager, names.into_iter().map(Into::into).collect()))
+            .collect();
+        let mut module = ModuleState::new(packages, BTreeMap::new());
+        for (path, info) in legacy.paths {
+            module.push_path(path, info.into());
+        }
+        module
+    }
+}
+
+/// Permissions weren't recorded, so they aren't checked for migrated paths.
+impl From<LegacyPathInfo> for PathInfo {
+    fn from(legacy: LegacyPathInfo) -> Self {
+        match legacy {
+            LegacyPathInfo::Directory => PathInfo::Directory { permissions: None },
+            LegacyPathInfo::File { size, hash } => PathInfo::File {
+                size,
+                hash,
+                permissions: None,
+            },
+            LegacyPathInfo::HardLink { size, hash } => PathInfo::HardLink {
+                size,
+                hash,
+                permissions: None,
+            },
+            LegacyPathInfo::Symlink { original } => PathInfo::Symlink { original },
+        }
+    }
+}
@@ -1,25 +1,31 @@
 use std::{
-    borrow::Cow,
     collections::{BTreeMap, BTreeSet, HashSet},
     fs::{self, File},
+    io::Write,
+    mem,
     path::{Path, PathBuf},
 };
 
 use anyhow::{Context, Result, bail};
 use bincode::{Decode, Encode, config::Configuration};
 use crossterm::style::Stylize;
+use legacy::LegacyState;
 use module::ModuleState;
 use path::PathInfo;
 
 use crate::{
+    accounts::{AccountOwners, Accounts, Group, User},
+    config,
     env::Env,
     globs::Globs,
-    module::{link::LinkMethod, set::ModuleSet},
-    packages::PackageManager,
+    module::{absent::Removal, hooks::HookKind, link::LinkMethod, set::ModuleSet},
+    packages::{Package, PackageManager},
+    services::{self, Service},
     source::{hashable::HashableSource, ident::SourceIdent},
     utils::pretty::Pretty,
 };
 
+mod legacy;
 pub mod module;
 pub mod path;
 
@@ -28,21 +34,45 @@ pub struct State {
     sources: BTreeMap<SourceIdent, HashableSource>,
     modules: BTreeMap<String, ModuleState>,
     paths: HashSet<PathBuf>,
+    accounts: AccountOwners,
 }
 
 impl State {
+    /// Written at the start of state files, followed by the version of their
+    /// format. Files without it use the format from before state files were
+    /// versioned.
+    const MAGIC: &[u8] = b"decster-state";
+    const VERSION: u32 = 1;
+
     pub fn load(env: &Env) -> Result<Self> {
         let dir = env.named_source_dir();
         fs::create_dir_all(dir)
             .with_context(|| format!("Couldn't create path: {}", env.tildefy(dir).pretty()))?;
-        Ok(File::open(env.state_file())
-            .ok()
-            .and_then(|mut file| bincode::decode_from_std_read(&mut file, Self::bin_config()).ok())
-            .unwrap_or_default())
+        let path = env.state_file();
+        let Ok(bytes) = fs::read(path) else {
+            return Ok(Self::default());
+        };
+        Self::decode(&bytes)
+            .with_context(|| format!("Couldn't read state file {}", env.tildefy(path).pretty()))
+    }
+
+    fn decode(bytes: &[u8]) -> Result<Self> {
+        let config = Self::bin_config();
+        let Some(bytes) = bytes.strip_prefix(Self::MAGIC) else {
+            let (state, _): (LegacyState, _) = bincode::decode_from_slice(bytes, config)?;
+            return Ok(state.into());
+        };
+        let (version, len): (u32, _) = bincode::decode_from_slice(bytes, config)?;
+        if version != Self::VERSION {
+            bail!("Unsupported version {version}");
+        }
+        Ok(bincode::decode_from_slice(&bytes[len..], config)?.0)
     }
 
     pub fn save(&self, env: &Env) -> Result<()> {
         let mut file = File::create(env.state_file())?;
+        file.write_all(Self::MAGIC)?;
+        bincode::encode_into_std_write(Self::VERSION, &mut file, Self::bin_config())?;
         bincode::encode_into_std_write(self, &mut file, Self::bin_config())?;
         Ok(())
     }
@@ -77,13 +107,34 @@ impl State {
         self.paths.contains(path.as_ref())
     }
 
-    pub fn add_module(&mut self, name: &str, packages: BTreeMap<PackageManager, BTreeSet<String>>) {
+    /// Returns whether `path` or any path inside of it is owned by a module.
+    pub fn is_path_or_child_owned(&self, path: &Path) -> bool {
+        self.paths.iter().any(|p| p.starts_with(path))
+    }
+
+    pub fn add_module(
+        &mut self,
+        name: &str,
+        packages: BTreeMap<PackageManager, BTreeSet<Package>>,
+        services: BTreeMap<String, Service>,
+    ) {
         if !self.modules.contains_key(name) {
-            let state = ModuleState::with_packages(packages);
+            let state = ModuleState::new(packages, services);
             self.modules.insert(name.to_string(), state);
         }
     }
 
+    /// Creates the users and groups of a module that don't exist yet.
+    pub fn create_accounts(
+        &mut self,
+        module: &str,
+        users: &BTreeMap<String, User>,
+        groups: &BTreeMap<String, Group>,
+    ) -> Result<()> {
+        let accounts = self.modules.get_mut(module).unwrap().accounts_mut();
+        self.accounts.create(module, accounts, users, groups)
+    }
+
     pub fn add_source(&mut self, ident: &SourceIdent, source: &HashableSource) {
         self.sources.insert(ident.clone(), source.clone());
     }
@@ -96,20 +147,79 @@ impl State {
             .push_path(path.to_path_buf(), info);
     }
 
-    /// The reason for the return type containing `Cow<str>` is that it's later
-    /// used in `PackageManager::diff`. That function uses
-    /// `BTreeSet::difference`, which can't compare `&str` with `String`.
-    pub fn packages(&self) -> BTreeMap<PackageManager, BTreeSet<Cow<str>>> {
-        let mut all_packages = BTreeMap::new();
-        for (manager, manager_packages) in
-            self.modules.iter().flat_map(|(_, state)| state.packages())
-        {
-            let packages: &mut BTreeSet<_> = all_packages.entry(manager).or_default();
-            for package in manager_packages {
-                packages.insert(Cow::Borrowed(package));
+    /// Records the permissions a module gave a path it added, after they've
+    /// been set.
+    pub fn record_permissions(&mut self, module: &str, path: &Path) -> Result<()> {
+        let paths = self.modules.get_mut(module).unwrap().paths_mut();
+        if let Some((_, info)) = paths.iter_mut().rev().find(|(p, _)| p == path) {
+            info.record_permissions(path)?;
+        }
+        Ok(())
+    }
+
+    pub fn add_removed_path(&mut self, module: &str, path: &Path, removal: Removal) {
+        self.modules
+            .get_mut(module)
+            .unwrap()
+            .removed_mut()
+            .push((path.to_path_buf(), removal));
+    }
+
+    /// Returns whether `path` is a directory that was left behind while its
+    /// module was being updated, and forgets it.
+    /// Adds a path to a module without taking ownership of it, since blocks
+    /// and merges of multiple modules can share a file, and directories that
+    /// already existed aren't removed along with the module.
+    pub fn add_shared_path(&mut self, module: &str, path: &Path, info: PathInfo) {
+        self.modules
+            .get_mut(module)
+            .unwrap()
+            .push_path(path.to_path_buf(), info);
+    }
+
+    pub fn packages(&self) -> BTreeMap<&PackageManager, BTreeSet<&Package>> {
+        self.packages_wanted_by(|_| true)
+    }
+
+    /// Returns the packages wanted by at least one module matching `globs`.
+    pub fn packages_matching_modules(
+        &self,
+        globs: &Globs,
+    ) -> BTreeMap<&PackageManager, BTreeSet<&Package>> {
+        self.packages_wanted_by(|modules| modules.iter().any(|module| globs.is_match(module)))
+    }
+
+    fn packages_wanted_by<F>(&self, filter: F) -> BTreeMap<&PackageManager, BTreeSet<&Package>>
+    where
+        F: Fn(&BTreeSet<&str>) -> bool,
+    {
+        self.package_owners()
+            .into_iter()
+            .filter_map(|(manager, owners)| {
+                let packages: BTreeSet<_> = owners
+                    .into_iter()
+                    .filter(|(_, modules)| filter(modules))
+                    .map(|(package, _)| package)
+                    .collect();
+                (!packages.is_empty()).then_some((manager, packages))
+            })
+            .collect()
+    }
+
+    /// Returns the packages wanted by enabled modules, along with the names of
+    /// the modules wanting them. A package is only uninstalled once no module
+    /// wants it.
+    pub fn package_owners(&self) -> BTreeMap<&PackageManager, BTreeMap<&Package, BTreeSet<&str>>> {
+        let mut owners: BTreeMap<_, BTreeMap<_, BTreeSet<_>>> = BTreeMap::new();
+        for (name, state) in self.modules.iter() {
+            for (manager, packages) in state.packages() {
+                let owners = owners.entry(manager).or_default();
+                for package in packages {
+                    owners.entry(package).or_default().insert(name.as_str());
+                }
             }
         }
-        all_packages
+        owners
     }
 
     pub fn modules(&self) -> impl Iterator<Item = (&str, &ModuleState)> {
@@ -136,12 +246,40 @@ impl State {
         name: &str,
         modules: ModuleSet,
         method: LinkMethod,
+    ) -> Result<()> {
+        modules.run_hooks(env, name, HookKind::PreEnable, &[])?;
+        self.enable_module_inner(env, name, &modules, method, &mut BTreeSet::new())?;
+        let state = &self.modules[name];
+        let paths: Vec<_> = state
+            .paths()
+            .iter()
+            .map(|(path, _)| path.as_path())
+            .collect();
+        // The module is enabled at this point, so later failures are only
+        // reported, and can be fixed by updating the module.
+        warn(services::start(&BTreeMap::new(), state.services(), &paths));
+        warn(modules.run_hooks(env, name, HookKind::PostEnable, &paths));
+        if !paths.is_empty() {
+            warn(modules.run_hooks(env, name, HookKind::OnChange, &paths));
+        }
+        Ok(())
+    }
+
+    /// Enables a module, taking over the directories in `left_dirs` if it
+    /// still creates them.
+    fn enable_module_inner(
+        &mut self,
+        env: &mut Env,
+        name: &str,
+        modules: &ModuleSet,
+        method: LinkMethod,
+        left_dirs: &mut BTreeSet<PathBuf>,
     ) -> Result<()> {
         if let Err(err) = modules
-            .enable(env, self, name, method)
+            .enable(env, self, name, method, left_dirs)
             .with_context(|| format!("Couldn't enable module {}", name.magenta()))
         {
-            if let Err(err) = self.disable_module(env, name) {
+            if let Err(err) = self.remove_module(env, name, &Accounts::default()) {
                 eprintln!("{} {err:?}", "error:".red());
             }
             bail!(err);
@@ -150,24 +288,61 @@ impl State {
     }
 
     pub fn disable_module(&mut self, env: &Env, module: &str) -> Result<()> {
+        let modules = config::module(module)
+            .map(|(name, module)| module.import(name))
+            .transpose()?;
+        // Failing hooks and units are only reported, since they might be why
+        // the module is being disabled.
+        if let Some(modules) = &modules {
+            warn(modules.run_hooks(env, module, HookKind::PreDisable, &[]));
+        }
+        warn(services::stop(
+            self.modules[module].services(),
+            &BTreeMap::new(),
+        ));
+        let paths = self.remove_module(env, module, &Accounts::default())?;
+        let paths: Vec<_> = paths.iter().map(|(path, _)| path.as_path()).collect();
+        warn(services::reload(&paths).map(drop));
+        if let Some(modules) = &modules {
+            warn(modules.run_hooks(env, module, HookKind::PostDisable, &paths));
+            if !paths.is_empty() {
+                warn(modules.run_hooks(env, module, HookKind::OnChange, &paths));
+            }
+        }
+        Ok(())
+    }
+
+    /// Removes the module and the paths it owns, and returns the removed paths.
+    /// Users and groups in `kept` stay owned by the module.
+    fn remove_module(
+        &mut self,
+        env: &Env,
+        module: &str,
+        kept: &Accounts,
+    ) -> Result<Vec<(PathBuf, PathInfo)>> {
         let state = self
             .modules
             .get_mut(module)
             .expect("Whether `name` exists should be checked before calling this method");
-        state.clear_packages();
         // Paths are removed in reverse order to make sure directories are
         // removed last.
         let paths = state.paths_mut();
-        for i in (0..paths.len()).rev() {
-            let (path, info) = &paths[i];
+        let mut removed = Vec::new();
+        while let Some((path, info)) = paths.last() {
             info.remove_if_owned(env, path)?;
             self.paths.remove(path);
-            paths.remove(i);
+            removed.extend(paths.pop());
         }
+        // Users and groups are removed after paths, since paths might be
+        // owned by them.
+        self.accounts.remove(module, kept)?;
         self.modules.remove(module);
-        Ok(())
+        Ok(removed)
     }
 
+    /// Disables and re-enables a module. Since the module stays enabled, only
+    /// its `on-change` hook is run, and only if any of its paths changed.
+    /// Likewise, only units whose settings or files changed are touched.
     pub fn update_module(
         &mut self,
         env: &mut Env,
@@ -175,10 +350,55 @@ impl State {
         modules: Option<ModuleSet>,
         method: LinkMethod,
     ) -> Result<()> {
-        self.disable_module(env, name)?;
-        if let Some(modules) = modules {
-            self.enable_module(env, name, modules, method)?;
+        let Some(modules) = modules else {
+            return self.disable_module(env, name);
+        };
+        let old_services = self.modules[name].services().clone();
+        warn(services::stop(&old_services, &modules.services()));
+        // Users and groups that are still wanted are kept instead of being
+        // removed and recreated.
+        let kept = Accounts::new(&modules.users(), &modules.groups());
+        let mut removed = mem::take(self.modules.get_mut(name).unwrap().removed_mut());
+        let old_paths = self.remove_module(env, name, &kept)?;
+        // Directories that still exist weren't empty, so the module has to
+        // take them over again to reapply their permissions.
+        let mut left_dirs = old_paths
+            .iter()
+            .filter(|(path, info)| matches!(info, PathInfo::Directory { .. }) && path.is_dir())
+            .map(|(path, _)| path.clone())
+            .collect();
+        self.enable_module_inner(env, name, &modules, method, &mut left_dirs)?;
+        // Paths removed earlier are still reported, unless they were removed
+        // again.
+        let new_removed = self.modules.get_mut(name).unwrap().removed_mut();
+        removed.retain(|(path, _)| !new_removed.iter().any(|(p, _)| p == path));
+        removed.append(new_removed);
+        *new_removed = removed;
+        let state = &self.modules[name];
+        let new_paths = state.paths();
+        let mut changed: Vec<_> = new_paths
+            .iter()
+            .filter(|(path, info)| !old_paths.iter().any(|(p, i)| p == path && i == info))
+            .map(|(path, _)| path.as_path())
+            .collect();
+        changed.extend(
+            old_paths
+                .iter()
+                .filter(|(path, _)| !new_paths.iter().any(|(p, _)| p == path))
+                .map(|(path, _)| path.as_path()),
+        );
+        warn(services::start(&old_services, state.services(), &changed));
+        if !changed.is_empty() {
+            warn(modules.run_hooks(env, name, HookKind::OnChange, &changed));
         }
         Ok(())
     }
 }
+
+/// Prints the error of a step that doesn't stop a module from being enabled or
+/// disabled as a warning.
+fn warn(result: Result<()>) {
+    if let Err(err) = result {
+        eprintln!("{} {err:?}", "warning:".yellow());
+    }
+}
@@ -8,26 +8,60 @@ use bincode::{Decode, Encode};
 use crossterm::style::Stylize;
 use termtree::Tree;
 
-use crate::{globs::Globs, packages::PackageManager, utils::pretty::Pretty};
+use crate::{
+    accounts::Accounts,
+    globs::Globs,
+    module::absent::Removal,
+    packages::{Package, PackageManager},
+    services::Service,
+    utils::pretty::Pretty,
+};
 
-use super::path::PathInfo;
+use super::path::{PathInfo, PathState};
 
 #[derive(Decode, Encode)]
 pub struct ModuleState {
     paths: Vec<(PathBuf, PathInfo)>,
-    packages: BTreeMap<PackageManager, BTreeSet<String>>,
+    packages: BTreeMap<PackageManager, BTreeSet<Package>>,
+    services: BTreeMap<String, Service>,
+    accounts: Accounts,
+    /// Paths declared as absent that were removed.
+    removed: Vec<(PathBuf, Removal)>,
 }
 
 impl ModuleState {
-    pub fn with_packages(packages: BTreeMap<PackageManager, BTreeSet<String>>) -> Self {
+    pub fn new(
+        packages: BTreeMap<PackageManager, BTreeSet<Package>>,
+        services: BTreeMap<String, Service>,
+    ) -> Self {
         let paths = Vec::new();
-        Self { paths, packages }
+        Self {
+            paths,
+            packages,
+            services,
+            accounts: Accounts::default(),
+            removed: Vec::new(),
+        }
     }
 
-    pub fn packages(&self) -> impl Iterator<Item = (PackageManager, impl Iterator<Item = &str>)> {
-        self.packages
-            .iter()
-            .map(|(manager, packages)| (*manager, packages.iter().map(|s| s.as_str())))
+    pub fn packages(&self) -> impl Iterator<Item = (&PackageManager, &BTreeSet<Package>)> {
+        self.packages.iter()
+    }
+
+    pub fn services(&self) -> &BTreeMap<String, Service> {
+        &self.services
+    }
+
+    pub fn accounts_mut(&mut self) -> &mut Accounts {
+        &mut self.accounts
+    }
+
+    pub fn removed_mut(&mut self) -> &mut Vec<(PathBuf, Removal)> {
+        &mut self.removed
+    }
+
+    pub fn paths(&self) -> &[(PathBuf, PathInfo)] {
+        &self.paths
     }
 
     pub fn paths_mut(&mut self) -> &mut Vec<(PathBuf, PathInfo)> {
@@ -38,16 +72,23 @@ impl ModuleState {
         self.paths.push((path, info));
     }
 
-    pub fn clear_packages(&mut self) {
-        self.packages.clear();
-    }
-
     pub fn tree<'a>(&'a self, name: &'a str, globs: &Globs) -> Option<Tree<Cow<'a, str>>> {
         let paths: Vec<_> = self
             .paths
             .iter()
             .filter(|(path, _)| globs.is_match(path))
-            .map(|(path, info)| format!("{} ({})", path.pretty(), info.kind()).into())
+            .map(|(path, info)| {
+                let state = match info.state(path) {
+                    PathState::Matches => String::new(),
+                    PathState::PermissionsDiffer { expected, actual } => format!(
+                        ", {} {expected}, found {actual}",
+                        "Permissions changed, expected".yellow()
+                    ),
+                    PathState::Differs => format!(", {}", "Changed".yellow()),
+                    PathState::Missing => format!(", {}", "Missing".red()),
+                };
+                format!("{} ({}{state})", path.pretty(), info.kind()).into()
+            })
             .map(Tree::new)
             .collect();
         let packages: Vec<_> = self
@@ -56,17 +97,54 @@ impl ModuleState {
             .filter_map(|(manager, packages)| {
                 let packages: Vec<Cow<_>> = packages
                     .iter()
-                    .filter(|package| globs.is_match(package))
-                    .map(|s| s.as_str().magenta().to_string().into())
+                    .filter(|package| globs.is_match(&package.name))
+                    .map(|package| package.to_string().into())
                     .collect();
                 (!packages.is_empty())
                     .then(|| Tree::new(manager.to_string().into()).with_leaves(packages))
             })
             .collect();
+        let services: Vec<_> = self
+            .services
+            .iter()
+            .filter(|(unit, _)| globs.is_match(unit))
+            .map(|(unit, service)| format!("{} ({service})", unit.as_str().magenta()).into())
+            .map(Tree::new)
+            .collect();
+        let removed: Vec<_> = self
+            .removed
+            .iter()
+            .filter(|(path, _)| globs.is_match(path))
+            .map(|(path, removal)| match removal {
+                Removal::Deleted => path.pretty().to_string().into(),
+                Removal::MovedTo(backup) => {
+                    format!("{} (Moved to {})", path.pretty(), backup.pretty()).into()
+                }
+            })
+            .map(Tree::new)
+            .collect();
+        let users: Vec<_> = self
+            .accounts
+            .users
+            .iter()
+            .filter(|user| globs.is_match(user))
+            .map(|user| Tree::new(user.as_str().magenta().to_string().into()))
+            .collect();
+        let groups: Vec<_> = self
+            .accounts
+            .groups
+            .iter()
+            .filter(|group| globs.is_match(group))
+            .map(|group| Tree::new(group.as_str().magenta().to_string().into()))
+            .collect();
 
         let leaves = [
             (!paths.is_empty()).then(|| Tree::new("Paths".into()).with_leaves(paths)),
             (!packages.is_empty()).then(|| Tree::new("Packages".into()).with_leaves(packages)),
+            (!services.is_empty()).then(|| Tree::new("Services".into()).with_leaves(services)),
+            (!removed.is_empty()).then(|| Tree::new("Removed".into()).with_leaves(removed)),
+            (!users.is_empty()).then(|| Tree::new("Users".into()).with_leaves(users)),
+            (!groups.is_empty()).then(|| Tree::new("Groups".into()).with_leaves(groups)),
         ]
         .into_iter()
         .flatten();
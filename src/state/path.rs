@@ -1,58 +1,178 @@
 use std::{
     fmt::{self, Display, Formatter},
-    fs,
-    os::unix::fs::MetadataExt,
+    fs::{self, Metadata},
+    os::unix::{self, fs::MetadataExt},
     path::{Path, PathBuf},
 };
 
-use anyhow::Result;
+use anyhow::{Context, Result};
 use bincode::{Decode, Encode};
 use crossterm::style::Stylize;
 
 use crate::{
     env::Env,
+    fs::mode::Mode,
+    module::{block::BlockMarkers, merge},
     utils::{pretty::Pretty, sha256::Sha256Hash},
 };
 
-#[derive(Decode, Encode)]
+/// The owner and mode a module gave a path it created.
+#[derive(Clone, Copy, Decode, Encode, PartialEq)]
+pub struct PathPermissions {
+    uid: u32,
+    gid: u32,
+    mode: u16,
+}
+
+impl PathPermissions {
+    fn from_metadata(metadata: &Metadata) -> Self {
+        PathPermissions {
+            uid: metadata.uid(),
+            gid: metadata.gid(),
+            mode: metadata.mode() as u16 & 0o7777,
+        }
+    }
+
+    pub fn from_path(path: &Path) -> Result<Self> {
+        let metadata = path
+            .symlink_metadata()
+            .with_context(|| format!("Couldn't read metadata of {}", path.pretty()))?;
+        Ok(Self::from_metadata(&metadata))
+    }
+
+    fn set(&self, env: &Env, path: &Path) -> Result<()> {
+        unix::fs::lchown(path, Some(self.uid), Some(self.gid))
+            .with_context(|| format!("Couldn't set owner of {}", env.tildefy(path).pretty()))?;
+        Mode::absolute(self.mode).set(env, path)
+    }
+}
+
+impl Display for PathPermissions {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{} {}:{}", Mode::absolute(self.mode), self.uid, self.gid)
+    }
+}
+
+/// Permissions are `None` until they're recorded, which happens after they've
+/// been set.
+#[derive(Decode, Encode, PartialEq)]
 pub enum PathInfo {
-    Directory,
-    File { size: u64, hash: Sha256Hash },
-    HardLink { size: u64, hash: Sha256Hash },
-    Symlink { original: PathBuf },
+    Directory {
+        permissions: Option<PathPermissions>,
+    },
+    /// A directory that existed before the module was enabled. It isn't owned
+    /// by the module, so it's never removed, but its original owner and mode
+    /// are restored unless they've changed since.
+    ExistingDirectory {
+        original: PathPermissions,
+        permissions: Option<PathPermissions>,
+    },
+    File {
+        size: u64,
+        hash: Sha256Hash,
+        permissions: Option<PathPermissions>,
+    },
+    HardLink {
+        size: u64,
+        hash: Sha256Hash,
+        permissions: Option<PathPermissions>,
+    },
+    Symlink {
+        original: PathBuf,
+    },
+    /// A block inside of a file, which isn't owned by the module itself.
+    Block {
+        markers: BlockMarkers,
+        hash: Sha256Hash,
+    },
+    /// Values merged into a file, stored as TOML. The file isn't owned by the
+    /// module itself, but it's removed along with the values if the module
+    /// created it and nothing else is left in it. Keys that were set before
+    /// the merge get their previous values back, which are stored as TOML as
+    /// well.
+    Merge {
+        values: String,
+        previous: String,
+        created: bool,
+    },
 }
 
 impl PathInfo {
     pub fn kind(&self) -> PathKind {
         match self {
-            PathInfo::Directory => PathKind::Directory,
+            PathInfo::Directory { .. } | PathInfo::ExistingDirectory { .. } => PathKind::Directory,
             PathInfo::File { .. } => PathKind::File,
             PathInfo::HardLink { .. } => PathKind::HardLink,
             PathInfo::Symlink { .. } => PathKind::Symlink,
+            PathInfo::Block { .. } => PathKind::Block,
+            PathInfo::Merge { .. } => PathKind::Merge,
         }
     }
 
+    fn permissions(&self) -> Option<PathPermissions> {
+        match self {
+            PathInfo::Directory { permissions }
+            | PathInfo::ExistingDirectory { permissions, .. }
+            | PathInfo::File { permissions, .. }
+            | PathInfo::HardLink { permissions, .. } => *permissions,
+            _ => None,
+        }
+    }
+
+    /// Records the current owner and mode of `path`, if the kind of the path
+    /// has permissions of its own.
+    pub fn record_permissions<P>(&mut self, path: P) -> Result<()>
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref();
+        if let PathInfo::Directory { permissions }
+        | PathInfo::ExistingDirectory { permissions, .. }
+        | PathInfo::File { permissions, .. }
+        | PathInfo::HardLink { permissions, .. } = self
+        {
+            *permissions = Some(PathPermissions::from_path(path)?);
+        }
+        Ok(())
+    }
+
     pub fn state<P>(&self, path: P) -> PathState
     where
         P: AsRef<Path>,
     {
         let path = path.as_ref();
         if let Ok(metadata) = path.symlink_metadata() {
-            if match self {
-                PathInfo::Directory => path.is_dir(),
-                PathInfo::File { size, hash } => {
+            if !match self {
+                PathInfo::Directory { .. } | PathInfo::ExistingDirectory { .. } => path.is_dir(),
+                PathInfo::File { size, hash, .. } => {
                     metadata.size() == *size
                         && Sha256Hash::from_file(path).is_ok_and(|h| h == *hash)
                 }
-                PathInfo::HardLink { size, hash } => {
+                PathInfo::HardLink { size, hash, .. } => {
                     metadata.size() == *size
                         && Sha256Hash::from_file(path).is_ok_and(|h| h == *hash)
                 }
                 PathInfo::Symlink { original } => path.read_link().is_ok_and(|o| o == *original),
+                PathInfo::Block { markers, hash } => {
+                    let Some(contents) = fs::read_to_string(path)
+                        .ok()
+                        .and_then(|text| markers.contents(&text).map(Sha256Hash::from_bytes))
+                    else {
+                        return PathState::Missing;
+                    };
+                    contents == *hash
+                }
+                PathInfo::Merge { values, .. } => toml::from_str(values)
+                    .is_ok_and(|values| merge::is_merged(path, &values).unwrap_or(false)),
             } {
-                PathState::Matches
-            } else {
                 PathState::Differs
+            } else if let Some(expected) = self.permissions()
+                && let actual = PathPermissions::from_metadata(&metadata)
+                && expected != actual
+            {
+                PathState::PermissionsDiffer { expected, actual }
+            } else {
+                PathState::Matches
             }
         } else {
             PathState::Missing
@@ -64,10 +184,53 @@ impl PathInfo {
         P: AsRef<Path>,
     {
         let path = path.as_ref();
+        if let PathInfo::Merge {
+            values,
+            previous,
+            created,
+        } = self
+            && path.exists()
+        {
+            // Merged keys are removed individually, so keys that haven't
+            // changed are removed even if others have.
+            let values = toml::from_str(values)?;
+            let original = merge::Original {
+                created: *created,
+                values: toml::from_str(previous)?,
+            };
+            if !merge::unmerge(path, &values, &original)
+                .with_context(|| format!("Couldn't remove merged keys from {}", path.pretty()))?
+            {
+                eprintln!(
+                    "{} Skipped changed keys in {}",
+                    "info:".yellow(),
+                    env.tildefy(path).pretty()
+                );
+            }
+            return Ok(());
+        }
         match self.state(path) {
-            PathState::Matches => {
-                if let PathKind::Directory = self.kind() {
+            state @ (PathState::Matches | PathState::PermissionsDiffer { .. }) => {
+                if let PathState::PermissionsDiffer { expected, actual } = state {
+                    eprintln!(
+                        "{} Permissions of {} have changed (Expected {expected}, found {actual})",
+                        "info:".yellow(),
+                        env.tildefy(path).pretty()
+                    );
+                }
+                if let PathInfo::ExistingDirectory { original, .. } = self {
+                    if state == PathState::Matches {
+                        original.set(env, path)?;
+                    }
+                } else if let PathKind::Directory = self.kind() {
                     let _ = fs::remove_dir(path);
+                } else if let PathInfo::Block { markers, .. } = self {
+                    // Files that only contained the block are removed.
+                    match markers.remove(&fs::read_to_string(path)?) {
+                        Some(text) if text.is_empty() => fs::remove_file(path)?,
+                        Some(text) => fs::write(path, text)?,
+                        None => (),
+                    }
                 } else {
                     fs::remove_file(path)?;
                 }
@@ -96,6 +259,8 @@ pub enum PathKind {
     File,
     HardLink,
     Symlink,
+    Block,
+    Merge,
 }
 
 impl Display for PathKind {
@@ -105,6 +270,8 @@ impl Display for PathKind {
             PathKind::File => "File".blue(),
             PathKind::HardLink => "Hard link".blue(),
             PathKind::Symlink => "Symlink".blue(),
+            PathKind::Block => "Block".blue(),
+            PathKind::Merge => "Merge".blue(),
         }
         .fmt(f)
     }
@@ -113,6 +280,12 @@ impl Display for PathKind {
 #[derive(PartialEq)]
 pub enum PathState {
     Matches,
+    /// The path's contents match, but its owner or mode has changed since it
+    /// was created.
+    PermissionsDiffer {
+        expected: PathPermissions,
+        actual: PathPermissions,
+    },
     Differs,
     Missing,
 }
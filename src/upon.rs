@@ -1,20 +1,67 @@
-use std::{collections::HashMap, sync::OnceLock};
+use std::{
+    borrow::Cow,
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    sync::{LazyLock, Mutex, OnceLock},
+};
 
-use anyhow::Result;
+use anyhow::{Context, Result, anyhow};
 use thiserror::Error;
-use toml::Value;
-use upon::Engine;
-
-static ENGINE: OnceLock<Engine> = OnceLock::new();
-
-fn engine() -> &'static Engine<'static> {
-    match ENGINE.get() {
-        Some(engine) => engine,
-        None => {
-            let engine = Engine::new();
-            ENGINE.get_or_init(|| engine)
-        }
+use toml::Table;
+use upon::{Engine, Template};
+
+use crate::{
+    env::Env,
+    utils::{pretty::Pretty, sha256::Sha256Hash},
+};
+
+static ENGINE: OnceLock<Result<Engine, String>> = OnceLock::new();
+static TEMPLATES: LazyLock<Mutex<HashMap<Sha256Hash, Template<'static>>>> =
+    LazyLock::new(Mutex::default);
+
+/// Creates the template engine and registers every file in the template
+/// directory, so they can be used with `{% include "<path>" %}`, where `<path>`
+/// is relative to the template directory. Errors are returned once a template
+/// is rendered, so commands that don't render anything still work.
+pub fn load(env: &Env) {
+    let engine = load_engine(env).map_err(|err| format!("{err:#}"));
+    if ENGINE.set(engine).is_err() {
+        panic!("`upon::load` should only be called once");
+    }
+}
+
+fn load_engine(env: &Env) -> Result<Engine<'static>> {
+    let mut engine = Engine::new();
+    let dir = env.template_dir();
+    if dir.is_dir() {
+        crate::fs::walk_dir_rel(dir, true, false, |path, rel_path| {
+            if path.is_file() {
+                add_template(&mut engine, path, rel_path).with_context(|| {
+                    format!("Couldn't load template {}", env.tildefy(path).pretty())
+                })?;
+            }
+            Ok(())
+        })?;
     }
+    Ok(engine)
+}
+
+fn add_template(engine: &mut Engine<'static>, path: &Path, rel_path: &Path) -> Result<()> {
+    let name = rel_path.to_string_lossy().into_owned();
+    let template = fs::read_to_string(path)?;
+    engine
+        .add_template(name, template)
+        .map_err(TemplateError::Compile)?;
+    Ok(())
+}
+
+fn engine() -> Result<&'static Engine<'static>> {
+    ENGINE
+        .get()
+        .expect("`upon::load` should be called before templates are rendered")
+        .as_ref()
+        .map_err(|err| anyhow!("{err}"))
 }
 
 /// Error type that wraps `upon::Error` to enable pretty printing in `anyhow`
@@ -27,12 +74,49 @@ enum TemplateError {
     Render(upon::Error),
 }
 
-pub fn render(template: &str, context: &HashMap<&str, &Value>) -> Result<String> {
-    let engine = engine();
-    Ok(engine
-        .compile(template)
-        .map_err(TemplateError::Compile)?
+/// Renders a template. Templates without any tags are returned as is.
+pub fn render(template: &str, context: &Table) -> Result<String> {
+    if !template.contains('{') {
+        return Ok(template.to_string());
+    }
+    let engine = engine()?;
+    let compiled = engine.compile(template).map_err(TemplateError::Compile)?;
+    Ok(compiled
+        .render(engine, context)
+        .to_string()
+        .map_err(TemplateError::Render)?)
+}
+
+/// Renders the contents of a templated source. Compiled sources are cached by
+/// the hash of their contents, since the same source is often rendered by
+/// several links. Templates without any tags are returned as is.
+pub fn render_source(template: &str, context: &Table) -> Result<String> {
+    if !template.contains('{') {
+        return Ok(template.to_string());
+    }
+    let engine = engine()?;
+    let hash = Sha256Hash::from_bytes(template);
+    let mut templates = TEMPLATES.lock().unwrap();
+    if !templates.contains_key(&hash) {
+        let compiled = engine
+            .compile(template.to_string())
+            .map_err(TemplateError::Compile)?;
+        templates.insert(hash.clone(), compiled);
+    }
+    Ok(templates[&hash]
         .render(engine, context)
         .to_string()
         .map_err(TemplateError::Render)?)
 }
+
+/// Renders a path as a template. Paths that aren't valid UTF-8 or don't change
+/// when rendered are returned as is.
+pub fn render_path<'a>(path: &'a Path, context: &Table) -> Result<Cow<'a, Path>> {
+    Ok(match path.to_str() {
+        Some(string) => match render(string, context)? {
+            render if render == string => Cow::Borrowed(path),
+            render => Cow::Owned(PathBuf::from(render)),
+        },
+        None => Cow::Borrowed(path),
+    })
+}
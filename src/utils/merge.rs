@@ -0,0 +1,56 @@
+use toml::{Table, Value};
+
+/// Merges `overrides` into `base`.
+///
+/// Tables that exist in both are merged recursively, while every other value
+/// in `overrides` replaces the value in `base`.
+pub fn merge_tables(base: &mut Table, overrides: &Table) {
+    for (key, value) in overrides {
+        match (base.get_mut(key), value) {
+            (Some(Value::Table(base)), Value::Table(overrides)) => merge_tables(base, overrides),
+            _ => {
+                base.insert(key.clone(), value.clone());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use toml::toml;
+
+    #[test]
+    fn merge() {
+        let mut base = toml! {
+            a = 1
+            b = [1, 2]
+            [c]
+            d = "base"
+            e = "base"
+            [c.f]
+            g = true
+        };
+        let overrides = toml! {
+            b = [3]
+            h = 2
+            [c]
+            e = "override"
+            [c.f]
+            i = false
+        };
+        merge_tables(&mut base, &overrides);
+        let merged = toml! {
+            a = 1
+            b = [3]
+            h = 2
+            [c]
+            d = "base"
+            e = "override"
+            [c.f]
+            g = true
+            i = false
+        };
+        assert_eq!(base, merged);
+    }
+}
@@ -1,2 +1,4 @@
+pub mod merge;
 pub mod pretty;
 pub mod sha256;
+pub mod version;
@@ -0,0 +1,311 @@
+use std::{
+    cmp::Ordering,
+    fmt::{self, Display, Formatter},
+    str::FromStr,
+};
+
+use bincode::{Decode, Encode};
+use serde::{Deserialize, Deserializer, de};
+use thiserror::Error;
+
+/// A version split into its parts, like `1:2.0-3`. Versions without an epoch
+/// have an epoch of 0, and the release is the package's own revision, which
+/// package managers append to the upstream version.
+struct Version<'a> {
+    epoch: u64,
+    upstream: &'a str,
+    release: Option<&'a str>,
+}
+
+impl<'a> Version<'a> {
+    fn parse(version: &'a str) -> Self {
+        let (epoch, rest) = match version.split_once(':') {
+            Some((epoch, rest)) if let Ok(epoch) = epoch.parse() => (epoch, rest),
+            _ => (0, version),
+        };
+        let (upstream, release) = match rest.rsplit_once('-') {
+            Some((upstream, release)) => (upstream, Some(release)),
+            None => (rest, None),
+        };
+        Version {
+            epoch,
+            upstream,
+            release,
+        }
+    }
+
+    /// Compares two versions. Epochs are compared first, then upstream
+    /// versions and then releases, where a version without a release is older
+    /// than one with a release.
+    fn compare(&self, other: &Version) -> Ordering {
+        self.epoch
+            .cmp(&other.epoch)
+            .then_with(|| compare_segments(self.upstream, other.upstream))
+            .then_with(|| match (self.release, other.release) {
+                (Some(a), Some(b)) => compare_segments(a, b),
+                (a, b) => a.is_some().cmp(&b.is_some()),
+            })
+    }
+}
+
+/// Compares two versions by splitting them into runs of digits and letters,
+/// which are compared one by one. Numeric runs are compared as numbers and are
+/// considered newer than letters, and any other characters are ignored.
+fn compare_segments(a: &str, b: &str) -> Ordering {
+    let (mut a, mut b) = (segments(a), segments(b));
+    loop {
+        let ord = match (a.next(), b.next()) {
+            (None, None) => return Ordering::Equal,
+            (Some(_), None) => return Ordering::Greater,
+            (None, Some(_)) => return Ordering::Less,
+            (Some(a), Some(b)) => match (a.parse::<u64>(), b.parse::<u64>()) {
+                (Ok(a), Ok(b)) => a.cmp(&b),
+                (Ok(_), Err(_)) => Ordering::Greater,
+                (Err(_), Ok(_)) => Ordering::Less,
+                (Err(_), Err(_)) => a.cmp(b),
+            },
+        };
+        if ord != Ordering::Equal {
+            return ord;
+        }
+    }
+}
+
+/// Returns whether every segment of `prefix` matches the corresponding segment
+/// of `version`, so `1.2` matches `1.2.3`.
+fn matches_prefix(version: &str, prefix: &str) -> bool {
+    let mut version = segments(version);
+    segments(prefix).all(|segment| {
+        version
+            .next()
+            .is_some_and(|other| compare_segments(segment, other) == Ordering::Equal)
+    })
+}
+
+fn segments(version: &str) -> impl Iterator<Item = &str> {
+    // Leading `v`s are skipped, so versions like `v1.2.3` can be compared with
+    // `1.2.3`.
+    let version = match version.strip_prefix('v') {
+        Some(rest) if rest.starts_with(|c: char| c.is_ascii_digit()) => rest,
+        _ => version,
+    };
+    let mut rest = version;
+    std::iter::from_fn(move || {
+        rest = rest.trim_start_matches(|c: char| !c.is_ascii_alphanumeric());
+        let first = rest.chars().next()?;
+        let end = rest
+            .find(|c: char| {
+                c.is_ascii_digit() != first.is_ascii_digit() || !c.is_ascii_alphanumeric()
+            })
+            .unwrap_or(rest.len());
+        let (segment, remaining) = rest.split_at(end);
+        rest = remaining;
+        Some(segment)
+    })
+}
+
+#[derive(Clone, Copy, Debug, Decode, Encode, Eq, Ord, PartialEq, PartialOrd)]
+enum Op {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl Op {
+    const ALL: [(&str, Op); 7] = [
+        ("==", Op::Eq),
+        ("!=", Op::Ne),
+        ("<=", Op::Le),
+        (">=", Op::Ge),
+        ("=", Op::Eq),
+        ("<", Op::Lt),
+        (">", Op::Gt),
+    ];
+}
+
+impl Display for Op {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Op::Eq => "=",
+            Op::Ne => "!=",
+            Op::Lt => "<",
+            Op::Le => "<=",
+            Op::Gt => ">",
+            Op::Ge => ">=",
+        }
+        .fmt(f)
+    }
+}
+
+#[derive(Clone, Debug, Decode, Encode, Eq, Ord, PartialEq, PartialOrd)]
+struct Comparator {
+    op: Op,
+    version: String,
+}
+
+impl Comparator {
+    /// Returns whether `version` matches. The release of `version` is only
+    /// compared if the comparator's version has one, since the release of an
+    /// installed package is rarely known in advance.
+    fn matches(&self, version: &str) -> bool {
+        let wanted = Version::parse(&self.version);
+        let mut version = Version::parse(version);
+        version.release = wanted.release.and(version.release);
+        let ord = version.compare(&wanted);
+        let matches_prefix = || {
+            version.epoch == wanted.epoch
+                && matches_prefix(version.upstream, wanted.upstream)
+                && version.release == wanted.release
+        };
+        match self.op {
+            Op::Eq => matches_prefix(),
+            Op::Ne => !matches_prefix(),
+            Op::Lt => ord == Ordering::Less,
+            Op::Le => ord != Ordering::Greater,
+            Op::Gt => ord == Ordering::Greater,
+            Op::Ge => ord != Ordering::Less,
+        }
+    }
+}
+
+/// A comma-separated list of version constraints, like `>=1.2, <2`.
+///
+/// A version without an operator, or with `=`, matches every version starting
+/// with it, so `=1.2` matches `1.2.3`.
+#[derive(Clone, Debug, Decode, Encode, Eq, Ord, PartialEq, PartialOrd)]
+pub struct VersionReq(Vec<Comparator>);
+
+impl VersionReq {
+    pub fn matches(&self, version: &str) -> bool {
+        self.0.iter().all(|comparator| comparator.matches(version))
+    }
+}
+
+impl Display for VersionReq {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        for (i, Comparator { op, version }) in self.0.iter().enumerate() {
+            if i != 0 {
+                ", ".fmt(f)?;
+            }
+            write!(f, "{op}{version}")?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Error, PartialEq)]
+pub enum ParseVersionReqError {
+    #[error("Version requirement is empty")]
+    Empty,
+    #[error("Version is missing after `{0}`")]
+    MissingVersion(String),
+    #[error("Version `{0}` contains invalid characters")]
+    InvalidVersion(String),
+}
+
+impl FromStr for VersionReq {
+    type Err = ParseVersionReqError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut comparators = Vec::new();
+        for part in s.split(',').map(str::trim) {
+            if part.is_empty() {
+                Err(ParseVersionReqError::Empty)?;
+            }
+            let (op, version) = Op::ALL
+                .iter()
+                .find_map(|(prefix, op)| part.strip_prefix(prefix).map(|rest| (*op, rest)))
+                .unwrap_or((Op::Eq, part));
+            let version = version.trim();
+            if version.is_empty() {
+                Err(ParseVersionReqError::MissingVersion(part.to_string()))?;
+            } else if version.contains(|c: char| c.is_whitespace() || "<>=!".contains(c)) {
+                Err(ParseVersionReqError::InvalidVersion(version.to_string()))?;
+            }
+            let version = version.to_string();
+            comparators.push(Comparator { op, version });
+        }
+        Ok(VersionReq(comparators))
+    }
+}
+
+impl<'de> Deserialize<'de> for VersionReq {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        VersionReq::from_str(&String::deserialize(deserializer)?).map_err(de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn compare_versions() {
+        let versions = [
+            ("1.0", "1.0", Ordering::Equal),
+            ("1.0", "1.0.0", Ordering::Less),
+            ("1.10", "1.9", Ordering::Greater),
+            ("0.10.0-1", "0.9.5-3", Ordering::Greater),
+            ("v14.1.0", "14.1.0", Ordering::Equal),
+            ("1.0a", "1.0b", Ordering::Less),
+            ("1.0.1", "1.0a", Ordering::Greater),
+            ("2:1.0", "1:2.0", Ordering::Greater),
+            ("1:0.9", "2.0", Ordering::Greater),
+            ("0:1.0", "1.0", Ordering::Equal),
+            ("0.10.0-1", "0.10.0", Ordering::Greater),
+            ("0.10.0-2", "0.10.0-10", Ordering::Less),
+        ];
+        for (a, b, ord) in versions {
+            assert_eq!(
+                Version::parse(a).compare(&Version::parse(b)),
+                ord,
+                "{a} {b}"
+            );
+        }
+    }
+
+    #[test]
+    fn parse_and_match() {
+        let reqs = [
+            (">=0.10", "0.10.0", true),
+            (">=0.10", "0.9.5", false),
+            (">=0.10, <0.11", "0.10.4", true),
+            (">=0.10, <0.11", "0.11.0", false),
+            ("0.10", "0.10.4-1", true),
+            ("=0.10", "0.1", false),
+            ("!=1.2", "1.2.3", false),
+            ("> 1", "1.0.1", true),
+            (">=0.10", "1:0.9", true),
+            (">=1:0.10", "1:0.9", false),
+            ("<=0.10.0", "0.10.0-1", true),
+            ("<=0.10.0-1", "0.10.0-2", false),
+            ("<0.10.0", "0.10.0-1", false),
+            ("=0.10.0-1", "0.10.0-1", true),
+            ("=0.10", "1:0.10.2", false),
+        ];
+        for (req, version, matches) in reqs {
+            let req = VersionReq::from_str(req).unwrap();
+            assert_eq!(req.matches(version), matches, "{req} {version}");
+        }
+
+        assert_eq!(VersionReq::from_str(""), Err(ParseVersionReqError::Empty));
+        assert_eq!(
+            VersionReq::from_str(">=1,"),
+            Err(ParseVersionReqError::Empty)
+        );
+        assert_eq!(
+            VersionReq::from_str(">="),
+            Err(ParseVersionReqError::MissingVersion(">=".to_string()))
+        );
+        assert_eq!(
+            VersionReq::from_str(">=1 2"),
+            Err(ParseVersionReqError::InvalidVersion("1 2".to_string()))
+        );
+    }
+}